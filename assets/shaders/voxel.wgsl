@@ -1,18 +1,40 @@
 #import bevy_pbr::mesh_functions::{get_model_matrix, mesh_position_local_to_clip}
+#import bevy_pbr::mesh_view_bindings::globals
 
 
 @group(2) @binding(0) var material_color_texture: texture_2d<f32>;
 @group(2) @binding(1) var material_color_sampler: sampler;
+#ifdef TEXTURE_ARRAY
+@group(2) @binding(2) var material_array_texture: texture_2d_array<f32>;
+@group(2) @binding(3) var material_array_sampler: sampler;
+#endif
 
 struct Vertex {
     @builtin(instance_index) instance_index: u32,
     @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
     @location(2) uv: vec2<f32>,
+#ifdef TEXTURE_ARRAY
+    @location(3) layer: u32,
+#else
+    @location(3) tile: vec4<f32>,
+#endif
+    @location(4) animation: vec4<f32>,
+    @location(5) occlusion: f32,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
+    @location(1) color: vec4<f32>,
     @location(2) uv: vec2<f32>,
+#ifdef TEXTURE_ARRAY
+    @location(3) @interpolate(flat) layer: u32,
+#else
+    @location(3) tile: vec4<f32>,
+#endif
+    @location(4) @interpolate(flat) animation: vec4<f32>,
+    // Light left by ambient occlusion, blended between the face corners
+    @location(5) occlusion: f32,
 };
 
 @vertex
@@ -22,15 +44,71 @@ fn vertex(vertex: Vertex) -> VertexOutput {
         get_model_matrix(vertex.instance_index),
         vec4<f32>(vertex.position, 1.0),
     );
+    out.color = vertex.color;
     out.uv = vertex.uv;
+#ifdef TEXTURE_ARRAY
+    out.layer = vertex.layer;
+#else
+    out.tile = vertex.tile;
+#endif
+    out.animation = vertex.animation;
+    out.occlusion = vertex.occlusion;
     return out;
 }
 
 struct FragmentInput {
+    // Tint, white for untinted faces
+    @location(1) color: vec4<f32>,
     @location(2) uv: vec2<f32>,
+#ifdef TEXTURE_ARRAY
+    @location(3) @interpolate(flat) layer: u32,
+#else
+    @location(3) tile: vec4<f32>,
+#endif
+    @location(4) @interpolate(flat) animation: vec4<f32>,
+    // Light left by ambient occlusion, blended between the face corners
+    @location(5) occlusion: f32,
 };
 
+// Explicit gradients, so frames can be sampled in non-uniform control flow
+fn sample_frame(mesh: FragmentInput, frame: f32, ddx: vec2<f32>, ddy: vec2<f32>) -> vec4<f32> {
+#ifdef TEXTURE_ARRAY
+    // uv is in blocks, the sampler repeats the layer once per block
+    let layer = mesh.layer + u32(frame * mesh.animation.w);
+    return textureSampleGrad(material_array_texture, material_array_sampler, mesh.uv, layer, ddx, ddy);
+#else
+    // uv is in blocks, repeat the atlas tile once per block
+    let uv = mesh.tile.xy + vec2(0.0, frame * mesh.animation.w) + fract(mesh.uv) * mesh.tile.zw;
+    return textureSampleGrad(material_color_texture, material_color_sampler, uv, ddx, ddy);
+#endif
+}
+
 @fragment
 fn fragment(mesh: FragmentInput) -> @location(0) vec4<f32> {
-    return vec4(1.) * textureSample(material_color_texture, material_color_sampler, mesh.uv) * vec4(1.);;
+    // Gradients of the unwrapped uv, fract would make them jump at block edges
+#ifdef TEXTURE_ARRAY
+    let uv = mesh.uv;
+#else
+    let uv = mesh.uv * mesh.tile.zw;
+#endif
+    let ddx = dpdx(uv);
+    let ddy = dpdy(uv);
+    // Still textures are a single frame
+    let frames = max(mesh.animation.x, 1.0);
+    let time = globals.time / max(mesh.animation.y, 0.001);
+    let frame = floor(time) % frames;
+    var color = sample_frame(mesh, frame, ddx, ddy);
+    // Only interpolated animations read the next frame
+    let blend = fract(time) * mesh.animation.z;
+    if frames > 1.0 && blend > 0.0 {
+        let next = (frame + 1.0) % frames;
+        color = mix(color, sample_frame(mesh, next, ddx, ddy), blend);
+    }
+    color *= mesh.color;
+#ifdef ALPHA_CUTOUT
+    if color.a < 0.5 {
+        discard;
+    }
+#endif
+    return vec4(color.rgb * mesh.occlusion, color.a);
 }
\ No newline at end of file
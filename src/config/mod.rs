@@ -7,6 +7,9 @@ use crate::prelude::*;
 #[derive(/*Serialize, Deserialize, */ Resource)]
 pub struct GameSettings {
     lang: LanguageSettings,
+    pub graphics: GraphicsSettings,
+    /// Extra asset directories with block packs, loaded after the built-in `blocks_types`
+    pub block_packs: Vec<String>,
 }
 
 #[derive(/*Serialize, Deserialize, */ Resource)]
@@ -14,11 +17,45 @@ pub struct LanguageSettings {
     lang: String,
 }
 
+pub struct GraphicsSettings {
+    /// Radius in chunks around the camera
+    pub render_distance: u32,
+    pub meshing: MeshingMode,
+    pub textures: TextureMode,
+    /// Face corners next to blocks are darkened
+    pub ambient_occlusion: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeshingMode {
+    /// Quad per visible block face
+    Naive,
+    /// Coplanar faces with the same texture merged into larger quads
+    Greedy,
+}
+
+/// How block textures are stored on the GPU
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TextureMode {
+    /// Single packed atlas sampled with nearest filtering
+    #[default]
+    Atlas,
+    /// Texture array of equally sized layers with mipmaps, no shimmering in the distance
+    Array,
+}
+
 pub fn load() -> GameSettings {
     // TODO load
     GameSettings {
         lang: LanguageSettings {
             lang: "en_EN".to_string(),
         },
+        graphics: GraphicsSettings {
+            render_distance: 8,
+            meshing: MeshingMode::Naive,
+            textures: TextureMode::Atlas,
+            ambient_occlusion: true,
+        },
+        block_packs: Vec::new(),
     }
 }
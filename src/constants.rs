@@ -1,3 +1,6 @@
 pub const CHUNK_W: usize = 16;
 pub const CHUNK_D: usize = 16;
 pub const CHUNK_H: usize = 128;
+/// Height of a chunk section, sections are stored, meshed and remeshed separately
+pub const SECTION_H: usize = 16;
+pub const SECTIONS: usize = CHUNK_H / SECTION_H;
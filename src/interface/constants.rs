@@ -1,3 +1,11 @@
 pub const CAMERA_SPEED: f32 = 15.;
 pub const CAMERA_SENTIVITY: f32 = 0.00012;
 pub const VOXEL_SIZE: f32 = 1.;
+/// Chunks generated per frame while streaming
+pub const CHUNK_SPAWN_BUDGET: usize = 4;
+/// Chunks despawned per frame while streaming
+pub const CHUNK_DESPAWN_BUDGET: usize = 16;
+/// Directory with all worlds
+pub const SAVES_DIR: &str = "saves";
+/// World opened until world selection exists
+pub const DEFAULT_WORLD: &str = "world";
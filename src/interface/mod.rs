@@ -6,9 +6,21 @@ mod player;
 mod render;
 mod resources;
 
-use render::voxel::{blocks::storage::BlockStorage, chunk::RenderOfChunk};
+use bevy::app::AppExit;
+use render::{camera::MainCamera, voxel::blocks::storage::BlockStorage};
 
-use crate::{prelude::*, voxel::chunks::chunk::Chunk};
+use crate::{
+    prelude::*,
+    voxel::{
+        blocks::registry::BlockRegistry,
+        chunks::region::WorldDir,
+        generator::{GeneratorKind, TerrainBlocks, WorldGen, DEFAULT_SEED},
+        world::{
+            level::{Level, PlayerPos},
+            Saves, WorldError,
+        },
+    },
+};
 
 pub struct InterfacePlugin;
 
@@ -19,34 +31,96 @@ impl Plugin for InterfacePlugin {
             render::RenderPlugin,
             player::PlayerPlugin,
         ))
-        .add_systems(OnEnter(GameState::Play), test_chunk);
+        .insert_resource(Saves::new(constants::SAVES_DIR))
+        .add_systems(OnEnter(GameState::Play), init_world)
+        .add_systems(
+            Last,
+            save_level.run_if(
+                on_event::<AppExit>()
+                    .and_then(resource_exists::<Level>)
+                    .and_then(resource_exists::<WorldDir>),
+            ),
+        );
     }
 }
 
-fn test_chunk(mut commands: Commands, storage: Res<BlockStorage>) {
-    let render = RenderOfChunk {
-        is_generated_mesh: false,
-        left_chunk: None,
-        right_chunk: None,
-        forward_chunk: None,
-        backward_chunk: None,
+/// Opens the default world, creates it on the first run.
+/// A world directory without a level gets a new one and keeps its chunks,
+/// an unreadable level stops the game so the file can be fixed
+fn init_world(
+    mut commands: Commands,
+    mut storage: ResMut<BlockStorage>,
+    saves: Res<Saves>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let entry = match saves.get(constants::DEFAULT_WORLD) {
+        Err(WorldError::NotFound(_)) if saves.world_dir(constants::DEFAULT_WORLD).is_dir() => saves
+            .adopt(
+                constants::DEFAULT_WORLD,
+                DEFAULT_SEED,
+                GeneratorKind::Terrain,
+            ),
+        Err(WorldError::NotFound(_)) => saves.create(
+            constants::DEFAULT_WORLD,
+            DEFAULT_SEED,
+            GeneratorKind::Terrain,
+        ),
+        entry => entry,
     };
-    let mut chk = Chunk::new_air(IVec2::splat(0));
-    for x in 0..CHUNK_W {
-        for y in 0..CHUNK_H {
-            for z in 0..CHUNK_D {
-                if y < 17 {
-                    chk.set(
-                        x,
-                        y,
-                        z,
-                        crate::voxel::blocks::Block::Solid(
-                            storage.get_id_by_name("grass".to_string()).unwrap().clone(),
-                        ),
-                    );
-                }
-            }
+    let mut entry = match entry {
+        Ok(entry) => entry,
+        Err(err) => {
+            error!("Failed to open world: {err}");
+            exit.send(AppExit);
+            return;
         }
+    };
+    let world = WorldDir(saves.world_dir(&entry.id));
+
+    let mut registry = match BlockRegistry::load(&world.0) {
+        Ok(registry) => registry,
+        Err(err) => {
+            // Saved chunks store ids of this registry, a new one would remap all of them.
+            // The file is left untouched so it can be fixed by hand
+            error!("Failed to load block registry, the world is not opened: {err}");
+            exit.send(AppExit);
+            return;
+        }
+    };
+    entry.level.touch();
+    if let Err(err) = entry.level.save(&world.0) {
+        error!("Failed to save level: {err}");
+    }
+    storage.bind_registry(&mut registry);
+    if let Err(err) = registry.save(&world.0) {
+        error!("Failed to save block registry: {err}");
+    }
+
+    let blocks =
+        TerrainBlocks::from_names(|name| storage.get_id_by_name(name.to_string()).copied())
+            .unwrap_or_else(|err| {
+                error!("{err}, generating `unknown` blocks instead");
+                TerrainBlocks::all(storage.unknown_id())
+            });
+    commands.insert_resource(WorldGen(
+        entry.level.generator.create(entry.level.seed, blocks),
+    ));
+    commands.insert_resource(entry.level);
+    commands.insert_resource(registry);
+    commands.insert_resource(world);
+}
+
+/// Stores where the player left the world
+fn save_level(
+    mut level: ResMut<Level>,
+    world: Res<WorldDir>,
+    cam: Query<&Transform, With<MainCamera>>,
+) {
+    if let Ok(cam) = cam.get_single() {
+        level.player = Some(PlayerPos::from_transform(cam));
+    }
+    level.touch();
+    if let Err(err) = level.save(&world.0) {
+        error!("Failed to save level: {err}");
     }
-    commands.spawn(chk).insert(render);
 }
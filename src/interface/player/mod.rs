@@ -13,7 +13,11 @@ use leafwing_input_manager::{
 
 use crate::{
     prelude::*,
-    voxel::{blocks::Block, chunks::chunk::Chunk},
+    voxel::{
+        blocks::{Block, BlockState, Side, StateProperties, StateProperty},
+        chunks::{chunk::Chunk, map::ChunkMap},
+        world::level::Level,
+    },
 };
 
 use super::{
@@ -39,9 +43,12 @@ impl Plugin for PlayerPlugin {
             )
                 .run_if(in_state(GameState::Play)),
         )
-        .insert_resource(SelectedBlock("grass".to_string()))
+        .insert_resource(SelectedBlock("core:grass".to_string()))
         .init_gizmo_group::<PointerGizmo>()
-        .add_systems(OnEnter(GameState::Play), set_cam_pos)
+        .add_systems(
+            OnEnter(GameState::Play),
+            set_cam_pos.after(super::init_world),
+        )
         .add_plugins(InputManagerPlugin::<PlayerActions>::default())
         .init_resource::<ActionState<PlayerActions>>()
         .insert_resource(PlayerActions::mkb_input_map());
@@ -100,9 +107,13 @@ impl PlayerActions {
 fn set_cam_pos(
     mut cam: Query<&mut Transform, With<MainCamera>>,
     mut config_store: ResMut<GizmoConfigStore>,
+    level: Res<Level>,
 ) {
     let mut cam = cam.single_mut();
-    cam.translation = Vec3::new(0., VOXEL_SIZE * 18., 0.);
+    *cam = match level.player {
+        Some(player) => player.to_transform(),
+        None => Transform::from_translation(Vec3::new(0., VOXEL_SIZE * 64., 0.)),
+    };
     let (my_config, _) = config_store.config_mut::<PointerGizmo>();
     my_config.render_layers = RenderLayers::none().with(1);
 }
@@ -114,16 +125,19 @@ pub struct SelectedBlock(String);
 
 fn select_block(keys: Res<ButtonInput<KeyCode>>, mut selected: ResMut<SelectedBlock>) {
     if keys.pressed(KeyCode::Digit1) {
-        selected.0 = "grass".to_string();
+        selected.0 = "core:grass".to_string();
     } else if keys.pressed(KeyCode::Digit2) {
-        selected.0 = "dirt".to_string();
+        selected.0 = "core:dirt".to_string();
     } else if keys.pressed(KeyCode::Digit3) {
-        selected.0 = "cobblestone".to_string();
+        selected.0 = "core:cobblestone".to_string();
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn player_action(
-    mut chunk: Query<(&mut Chunk, &mut RenderOfChunk)>,
+    mut chunks: Query<&mut Chunk>,
+    mut renders: Query<&mut RenderOfChunk>,
+    map: Res<ChunkMap>,
     cam: Query<&GlobalTransform, With<MainCamera>>,
     action_state: Res<ActionState<PlayerActions>>,
     primary_window: Query<&Window, With<PrimaryWindow>>,
@@ -143,7 +157,6 @@ fn player_action(
     let camera_transform = cam.single();
     pointer_gizmos.line_2d(Vec2::new(-10., 0.), Vec2::new(10., 0.), Color::WHITE);
     pointer_gizmos.line_2d(Vec2::new(0., -10.), Vec2::new(0., 10.), Color::WHITE);
-    let (mut chunk, mut render) = chunk.single_mut();
 
     let max_dist = 20.;
     let mut ray_pos = camera_transform.translation();
@@ -153,7 +166,7 @@ fn player_action(
     let mut prev_iray_pos = iray_pos.clone();
     let dir = camera_transform.forward() / 100.;
     while camera_transform.translation().distance(ray_pos) <= max_dist {
-        if let Some(Block::Solid(_)) = chunk.get_i32(iray_pos.x, iray_pos.y, iray_pos.z) {
+        if let Some(Block::Solid(..)) = map.get_block(iray_pos, &chunks) {
             break;
         }
         prev_iray_pos = iray_pos.clone();
@@ -172,25 +185,95 @@ fn player_action(
         },
         Color::BLACK,
     );
+    if action_state.just_pressed(&PlayerActions::PlaceBlock) {
+        // Blocks with `Open` state are used instead of built on
+        if let Some(Block::Solid(id, state)) = map.get_block(iray_pos, &chunks) {
+            if storage.properties(id).states.has(StateProperty::Open) {
+                let used = Block::Solid(id, state.with_open(!state.is_open()));
+                let edited = map.set_block(iray_pos, used, &mut chunks);
+                mark_edited(edited, iray_pos, &mut renders);
+                return;
+            }
+        }
+    }
     if action_state.just_pressed(&PlayerActions::PlaceBlock) && prev_iray_pos != iray_pos {
-        chunk.set_i32(
-            prev_iray_pos.x,
-            prev_iray_pos.y,
-            prev_iray_pos.z,
-            crate::voxel::blocks::Block::Solid(
-                storage.get_id_by_name(selected.0.clone()).unwrap().clone(),
-            ),
+        let id = *storage.get_id_by_name(selected.0.clone()).unwrap();
+        let state = placement_state(
+            storage.properties(id).states,
+            prev_iray_pos - iray_pos,
+            camera_transform.forward(),
+            ray_pos.y / VOXEL_SIZE,
         );
-        render.is_generated_mesh = false;
+        let edited = map.set_block(prev_iray_pos, Block::Solid(id, state), &mut chunks);
+        mark_edited(edited, prev_iray_pos, &mut renders);
     }
     if action_state.just_pressed(&PlayerActions::HurtBlock) {
-        chunk.set_i32(
-            iray_pos.x,
-            iray_pos.y,
-            iray_pos.z,
-            crate::voxel::blocks::Block::Air,
-        );
-        render.is_generated_mesh = false;
+        if let Some(Block::Solid(id, _)) = map.get_block(iray_pos, &chunks) {
+            if !storage.properties(id).breakable {
+                return;
+            }
+        }
+        let edited = map.set_block(iray_pos, Block::Air, &mut chunks);
+        mark_edited(edited, iray_pos, &mut renders);
+    }
+}
+
+/// State of a placed block from the face it was placed on (`normal`), where the player looks
+/// and the height of the hit point (in blocks). Only declared properties are set
+fn placement_state(declared: StateProperties, normal: IVec3, look: Vec3, hit_y: f32) -> BlockState {
+    let mut state = BlockState::default();
+    if declared.has(StateProperty::Facing) {
+        let facing = if look.x.abs() > look.z.abs() {
+            if look.x > 0. {
+                Side::Right
+            } else {
+                Side::Left
+            }
+        } else if look.z > 0. {
+            Side::Back
+        } else {
+            Side::Forward
+        };
+        state = state.with_facing(facing);
+    }
+    if declared.has(StateProperty::Axis) {
+        let axis = (0..3).max_by_key(|axis| normal[*axis].abs()).unwrap_or(1);
+        state = state.with_axis(axis);
+    }
+    if declared.has(StateProperty::Half) {
+        let top = match normal.y {
+            1 => false,
+            -1 => true,
+            _ => hit_y.fract().rem_euclid(1.) > 0.5,
+        };
+        state = state.with_top(top);
+    }
+    state
+}
+
+/// Requests remesh of edited chunk and neighbours sharing the edited border or corner
+fn mark_edited(edited: Option<Entity>, pos: IVec3, renders: &mut Query<&mut RenderOfChunk>) {
+    let Some(edited) = edited else {
+        return;
+    };
+    let local = ChunkMap::local_pos(pos);
+    let (neighbours, corner): (Vec<Entity>, _) = match renders.get_mut(edited) {
+        Ok(mut render) => {
+            render.mark_dirty(pos.y);
+            (
+                render.border_neighbours(local).collect(),
+                render.corner_neighbour(local),
+            )
+        }
+        Err(_) => return,
+    };
+    // Ambient occlusion of the diagonal chunk reads the shared corner column
+    let diagonal =
+        corner.and_then(|(x_neighbour, forward)| renders.get(x_neighbour).ok()?.z_link(forward));
+    for neighbour in neighbours.into_iter().chain(diagonal) {
+        if let Ok(mut render) = renders.get_mut(neighbour) {
+            render.mark_dirty(pos.y);
+        }
     }
 }
 fn move_camera(
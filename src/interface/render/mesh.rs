@@ -1,84 +1,165 @@
 use super::{
-    util::{square_mesh, SquareType3D},
-    voxel::blocks::storage::{BlockSideInfo, BlockSides, BlockStorage, BlockType},
+    util::{box_face, cross_faces, square_face, tile_rect, FaceTexture, SquareType3D},
+    voxel::blocks::storage::{BlockSideInfo, BlockSides, BlockStorage, BlockType, ModelFace},
 };
 use crate::{
-    interface::{constants::VOXEL_SIZE, resources::blocks::UnMeshedBlockType},
+    interface::{
+        constants::VOXEL_SIZE,
+        resources::blocks::{BlockFace, BlockFaces, ModelBox, UnMeshedBlockType},
+    },
     prelude::*,
+    voxel::blocks::{BlockProperties, Side},
 };
 
-pub fn meshing_block_type(
-    storage: &BlockStorage,
-    type_: &UnMeshedBlockType,
-    layouts: &Res<Assets<TextureAtlasLayout>>,
-) -> BlockType {
-    match type_ {
-        UnMeshedBlockType::Block { faces } => {
-            let left_rect = storage.imgs.get_texture_rect(&faces.left.clone(), layouts);
-            let right_rect = storage.imgs.get_texture_rect(&faces.right.clone(), layouts);
-            let top_rect = storage.imgs.get_texture_rect(&faces.top.clone(), layouts);
-            let bottom_rect = storage
-                .imgs
-                .get_texture_rect(&faces.bottom.clone(), layouts);
-            let forward_rect = storage
-                .imgs
-                .get_texture_rect(&faces.forward.clone(), layouts);
-            let back_rect = storage
-                .imgs
-                .get_texture_rect(&faces.backward.clone(), layouts);
+/// Model pixels per block side
+const MODEL_PIXELS: f32 = 16.;
+const ALL_SIDES: u8 = 0b11_1111;
 
+pub fn meshing_block_type(storage: &BlockStorage, type_: &UnMeshedBlockType) -> BlockType {
+    let properties = type_.properties();
+    match type_ {
+        UnMeshedBlockType::Block { faces, .. } => BlockType {
+            sides: cube_sides(storage, faces),
+            model: None,
+            full_sides: ALL_SIDES,
+            properties,
+        },
+        UnMeshedBlockType::Cross { texture, tint, .. } => {
+            let face = BlockFace {
+                texture: texture.clone(),
+                tint: *tint,
+            };
+            let texture = face_texture(storage, &face);
+            let faces = BlockFaces::all(face);
             BlockType {
-                sides: BlockSides {
-                    left: BlockSideInfo(square_mesh(
-                        VOXEL_SIZE / 2.,
-                        VOXEL_SIZE / 2.,
-                        VOXEL_SIZE / 2.,
-                        SquareType3D::Right(-1.),
-                        storage.imgs.texture_size,
-                        left_rect.clone(),
-                    )),
-                    right: BlockSideInfo(square_mesh(
-                        VOXEL_SIZE / 2.,
-                        VOXEL_SIZE / 2.,
-                        VOXEL_SIZE / 2.,
-                        SquareType3D::Right(1.),
-                        storage.imgs.texture_size,
-                        right_rect.clone(),
-                    )),
-                    top: BlockSideInfo(square_mesh(
-                        VOXEL_SIZE / 2.,
-                        VOXEL_SIZE / 2.,
-                        VOXEL_SIZE / 2.,
-                        SquareType3D::Top(1.),
-                        storage.imgs.texture_size,
-                        top_rect.clone(),
-                    )),
-                    bottom: BlockSideInfo(square_mesh(
-                        VOXEL_SIZE / 2.,
-                        VOXEL_SIZE / 2.,
-                        VOXEL_SIZE / 2.,
-                        SquareType3D::Top(-1.),
-                        storage.imgs.texture_size,
-                        bottom_rect.clone(),
-                    )),
-                    forward: BlockSideInfo(square_mesh(
-                        VOXEL_SIZE / 2.,
-                        VOXEL_SIZE / 2.,
-                        VOXEL_SIZE / 2.,
-                        SquareType3D::Back(-1.),
-                        storage.imgs.texture_size,
-                        forward_rect.clone(),
-                    )),
-                    back: BlockSideInfo(square_mesh(
-                        VOXEL_SIZE / 2.,
-                        VOXEL_SIZE / 2.,
-                        VOXEL_SIZE / 2.,
-                        SquareType3D::Back(1.),
-                        storage.imgs.texture_size,
-                        back_rect.clone(),
-                    )),
-                },
+                sides: cube_sides(storage, &faces),
+                model: Some(
+                    cross_faces(VOXEL_SIZE, texture)
+                        .into_iter()
+                        .map(|face| ModelFace { face, cull: None })
+                        .collect(),
+                ),
+                full_sides: 0,
+                properties,
             }
         }
+        UnMeshedBlockType::Slab { faces, .. } => model_type(storage, faces, &[SLAB], properties),
+        UnMeshedBlockType::Stairs { faces, .. } => {
+            model_type(storage, faces, &[SLAB, STEP], properties)
+        }
+        UnMeshedBlockType::Model { faces, boxes, .. } => {
+            model_type(storage, faces, boxes, properties)
+        }
+    }
+}
+
+const SLAB: ModelBox = ModelBox {
+    from: [0., 0., 0.],
+    to: [16., 8., 16.],
+};
+const STEP: ModelBox = ModelBox {
+    from: [0., 8., 8.],
+    to: [16., 16., 16.],
+};
+
+fn face_texture(storage: &BlockStorage, face: &BlockFace) -> FaceTexture {
+    let name = face.texture.as_str();
+    FaceTexture {
+        tile: tile_rect(
+            storage.imgs.texture_size,
+            storage.imgs.get_texture_rect(name),
+        ),
+        layer: storage.imgs.get_layer(name),
+        animation: storage.imgs.get_animation(name),
+        tint: face.tint,
+    }
+}
+
+fn face(faces: &BlockFaces, side: Side) -> &BlockFace {
+    match side {
+        Side::Left => &faces.left,
+        Side::Right => &faces.right,
+        Side::Bottom => &faces.bottom,
+        Side::Top => &faces.top,
+        Side::Forward => &faces.forward,
+        Side::Back => &faces.backward,
+    }
+}
+
+fn cube_sides(storage: &BlockStorage, faces: &BlockFaces) -> BlockSides {
+    let side = |face: &BlockFace, s_type| {
+        BlockSideInfo(square_face(
+            VOXEL_SIZE / 2.,
+            VOXEL_SIZE / 2.,
+            VOXEL_SIZE / 2.,
+            s_type,
+            face_texture(storage, face),
+        ))
+    };
+    BlockSides {
+        left: side(&faces.left, SquareType3D::Right(-1.)),
+        right: side(&faces.right, SquareType3D::Right(1.)),
+        top: side(&faces.top, SquareType3D::Top(1.)),
+        bottom: side(&faces.bottom, SquareType3D::Top(-1.)),
+        forward: side(&faces.forward, SquareType3D::Back(-1.)),
+        back: side(&faces.backward, SquareType3D::Back(1.)),
+    }
+}
+
+fn model_type(
+    storage: &BlockStorage,
+    faces: &BlockFaces,
+    boxes: &[ModelBox],
+    properties: BlockProperties,
+) -> BlockType {
+    let mut model = Vec::with_capacity(boxes.len() * 6);
+    for model_box in boxes {
+        let min = Vec3::from_array(model_box.from) / MODEL_PIXELS - 0.5;
+        let max = Vec3::from_array(model_box.to) / MODEL_PIXELS - 0.5;
+        for side in Side::ALL {
+            let texture = face_texture(storage, face(faces, side));
+            model.push(ModelFace {
+                face: box_face(min, max, side, VOXEL_SIZE, texture),
+                cull: on_border(model_box, side).then_some(side),
+            });
+        }
+    }
+    BlockType {
+        sides: cube_sides(storage, faces),
+        model: Some(model),
+        full_sides: Side::ALL
+            .into_iter()
+            .filter(|side| is_side_full(boxes, *side))
+            .fold(0, |bits, side| bits | side.bit()),
+        properties,
     }
 }
+
+/// Box face on `side` lies on the block border
+fn on_border(model_box: &ModelBox, side: Side) -> bool {
+    let (axis, positive) = side.axis();
+    if positive {
+        model_box.to[axis] >= MODEL_PIXELS
+    } else {
+        model_box.from[axis] <= 0.
+    }
+}
+
+/// Boxes on the border cover every pixel of `side`
+fn is_side_full(boxes: &[ModelBox], side: Side) -> bool {
+    let (axis, _) = side.axis();
+    let (a, b) = match axis {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    };
+    let pixels = MODEL_PIXELS as usize;
+    (0..pixels * pixels).all(|i| {
+        let (pa, pb) = ((i % pixels) as f32 + 0.5, (i / pixels) as f32 + 0.5);
+        boxes.iter().any(|model_box| {
+            on_border(model_box, side)
+                && (model_box.from[a]..model_box.to[a]).contains(&pa)
+                && (model_box.from[b]..model_box.to[b]).contains(&pb)
+        })
+    })
+}
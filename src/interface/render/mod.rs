@@ -1,5 +1,6 @@
 pub mod camera;
 mod mesh;
+mod tint;
 mod util;
 pub mod voxel;
 use crate::prelude::*;
@@ -0,0 +1,69 @@
+use crate::{
+    interface::resources::blocks::Tint,
+    prelude::*,
+    voxel::generator::{Climate, WorldGen},
+};
+
+/// sRGB grass colors of (cold, dry), (hot, dry), (cold, wet) and (hot, wet) climates
+const GRASS: [Vec3; 4] = [
+    Vec3::new(0.56, 0.71, 0.55),
+    Vec3::new(0.75, 0.71, 0.35),
+    Vec3::new(0.38, 0.62, 0.45),
+    Vec3::new(0.35, 0.73, 0.22),
+];
+/// sRGB foliage colors, same corners as [`GRASS`]
+const FOLIAGE: [Vec3; 4] = [
+    Vec3::new(0.45, 0.63, 0.45),
+    Vec3::new(0.68, 0.64, 0.25),
+    Vec3::new(0.30, 0.55, 0.38),
+    Vec3::new(0.22, 0.65, 0.12),
+];
+
+/// Climate at the block corners of a chunk, tinted vertices in between are interpolated
+pub struct ClimateMap {
+    /// `(CHUNK_W + 1) * (CHUNK_D + 1)`, indexed by `z * (CHUNK_W + 1) + x`
+    corners: Vec<Climate>,
+}
+
+impl ClimateMap {
+    /// Climate around the chunk at chunk coordinate `pos`, the default one without a generator
+    pub fn new(pos: IVec2, generator: Option<&WorldGen>) -> Self {
+        let mut corners = Vec::with_capacity((CHUNK_W + 1) * (CHUNK_D + 1));
+        for z in 0..=CHUNK_D as i32 {
+            for x in 0..=CHUNK_W as i32 {
+                corners.push(generator.map_or_else(Climate::default, |generator| {
+                    generator.climate(pos.x * CHUNK_W as i32 + x, pos.y * CHUNK_D as i32 + z)
+                }));
+            }
+        }
+        Self { corners }
+    }
+    /// Climate at chunk local position in blocks, block corners are integers
+    pub fn get(&self, x: f32, z: f32) -> Climate {
+        let (x, z) = (x.clamp(0., CHUNK_W as f32), z.clamp(0., CHUNK_D as f32));
+        let (x0, z0) = ((x as usize).min(CHUNK_W - 1), (z as usize).min(CHUNK_D - 1));
+        let (tx, tz) = (x - x0 as f32, z - z0 as f32);
+        let at = |x: usize, z: usize| self.corners[z * (CHUNK_W + 1) + x];
+        let back = at(x0, z0).lerp(at(x0 + 1, z0), tx);
+        let front = at(x0, z0 + 1).lerp(at(x0 + 1, z0 + 1), tx);
+        back.lerp(front, tz)
+    }
+    /// Linear vertex color of `tint` at chunk local position in blocks
+    pub fn color(&self, tint: Tint, x: f32, z: f32) -> [f32; 4] {
+        let srgb = match tint {
+            Tint::None => return [1.; 4],
+            Tint::Rgb(r, g, b) => Vec3::new(r, g, b),
+            // Unresolved by `Tint::in_state`, drawn as the default state
+            Tint::State { off: (r, g, b), .. } => Vec3::new(r, g, b),
+            Tint::Grass => climate_color(&GRASS, self.get(x, z)),
+            Tint::Foliage => climate_color(&FOLIAGE, self.get(x, z)),
+        };
+        Color::rgb(srgb.x, srgb.y, srgb.z).as_linear_rgba_f32()
+    }
+}
+
+fn climate_color(corners: &[Vec3; 4], climate: Climate) -> Vec3 {
+    let dry = corners[0].lerp(corners[1], climate.temperature);
+    let wet = corners[2].lerp(corners[3], climate.temperature);
+    dry.lerp(wet, climate.humidity)
+}
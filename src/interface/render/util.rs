@@ -1,21 +1,64 @@
-use crate::prelude::*;
+use super::tint::ClimateMap;
+use crate::{
+    config::TextureMode,
+    interface::constants::VOXEL_SIZE,
+    interface::resources::blocks::Tint,
+    prelude::*,
+    voxel::blocks::{BlockState, Side},
+};
 use bevy::render::{
-    mesh::{Indices, PrimitiveTopology},
+    mesh::{Indices, MeshVertexAttribute, PrimitiveTopology},
     render_asset::RenderAssetUsages,
+    render_resource::VertexFormat,
 };
-/// Mesh must have attrs:
-/// [`UV_0`] TODO!
-/// [`Indices`]
-/// [`POSITION`]
-
-pub fn void_mesh() -> Mesh {
-    Mesh::new(
-        PrimitiveTopology::TriangleList,
-        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-    )
-    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, Vec::<[f32; 3]>::new())
-    .with_inserted_indices(Indices::U32(Vec::<u32>::new()))
-    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, Vec::<[f32; 2]>::new())
+
+/// Atlas rect of the face texture (min.x, min.y, size.x, size.y) in UV space,
+/// [`UV_0`] is then the position on the face in blocks and the texture repeats every block
+pub const ATTRIBUTE_TILE: MeshVertexAttribute =
+    MeshVertexAttribute::new("Tile", 988540917, VertexFormat::Float32x4);
+
+/// Layer of the face texture in the block texture array, used instead of [`ATTRIBUTE_TILE`]
+/// in [`TextureMode::Array`]
+pub const ATTRIBUTE_LAYER: MeshVertexAttribute =
+    MeshVertexAttribute::new("Layer", 988540918, VertexFormat::Uint32);
+
+/// Frame count, seconds per frame, 1 to blend frames or 0, distance from a frame to the next one
+/// (in atlas V or in layers). The shader picks the frame from the time, so chunks aren't remeshed
+pub const ATTRIBUTE_ANIMATION: MeshVertexAttribute =
+    MeshVertexAttribute::new("Animation", 988540919, VertexFormat::Float32x4);
+
+/// Light left by ambient occlusion at a face corner, the darkest first
+pub const ATTRIBUTE_OCCLUSION: MeshVertexAttribute =
+    MeshVertexAttribute::new("Occlusion", 988540920, VertexFormat::Float32);
+
+/// [`ATTRIBUTE_OCCLUSION`] of a corner touching 3, 2, 1 or no occluding blocks
+const OCCLUSION_LIGHT: [f32; 4] = [0.45, 0.65, 0.82, 1.];
+
+/// Corner occlusion of faces without neighbours, see [`MeshBuffers::push_shaded_face`]
+pub const NO_OCCLUSION: [u8; 4] = [3; 4];
+
+/// Where the texture of a face is, in both texture modes
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FaceTexture {
+    /// See [`ATTRIBUTE_TILE`]
+    pub tile: [f32; 4],
+    /// See [`ATTRIBUTE_LAYER`]
+    pub layer: u32,
+    /// See [`ATTRIBUTE_ANIMATION`]
+    pub animation: [f32; 4],
+    /// Becomes the [`ATTRIBUTE_COLOR`](Mesh::ATTRIBUTE_COLOR) of the vertices
+    pub tint: Tint,
+}
+
+/// Normalized atlas rect for [`ATTRIBUTE_TILE`]
+pub fn tile_rect(image_size: UVec2, image_rect: Rect) -> [f32; 4] {
+    let size = image_size.as_vec2();
+    [
+        image_rect.min.x / size.x,
+        image_rect.min.y / size.y,
+        image_rect.width() / size.x,
+        image_rect.height() / size.y,
+    ]
 }
 
 #[derive(TypePath, Debug, serde::Deserialize)]
@@ -25,101 +68,258 @@ pub enum SquareType3D {
     Top(f32),   // +Y
 }
 
-#[allow(unused_assignments)]
-pub fn square_mesh(
+/// Quad of one block face, positions are relative to the block center
+#[derive(Clone, Copy, Debug)]
+pub struct FaceTemplate {
+    pub positions: [[f32; 3]; 4],
+    pub uvs: [[f32; 2]; 4],
+    pub indices: [u32; 6],
+    pub texture: FaceTexture,
+}
+
+impl FaceTemplate {
+    /// Face turned by `m` around the block center, winding is flipped for mirroring matrices
+    pub fn transformed(&self, m: Mat3) -> Self {
+        let mut indices = self.indices;
+        if m.determinant() < 0. {
+            for triangle in indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+        Self {
+            positions: self.positions.map(|p| (m * Vec3::from_array(p)).to_array()),
+            uvs: self.uvs,
+            indices,
+            texture: self.texture,
+        }
+    }
+}
+
+pub fn square_face(
     width: f32,
     height: f32,
     depth: f32,
     s_type: SquareType3D,
-    image_size: UVec2,
-    image_rect: Rect,
-) -> Mesh {
-    let prev_uv = (
-        [
-            image_rect.min.x / image_size.x as f32,
-            image_rect.min.y / image_size.y as f32,
-        ],
-        [
-            image_rect.max.x / image_size.x as f32,
-            image_rect.max.y / image_size.y as f32,
-        ],
-    );
-
-    let mut mesh = Mesh::new(
-        PrimitiveTopology::TriangleList,
-        RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-    );
-    let mut factor2 = 1.;
-    match s_type {
-        SquareType3D::Back(factor) => {
-            mesh.insert_attribute(
-                Mesh::ATTRIBUTE_POSITION,
-                vec![
-                    [-1., -1., 1. * factor],
-                    [-1., 1., 1. * factor],
-                    [1., 1., 1. * factor],
-                    [1., -1., 1. * factor],
-                ],
-            );
-            mesh.insert_attribute(
-                Mesh::ATTRIBUTE_UV_0,
-                vec![
-                    prev_uv.1,
-                    [prev_uv.1[0], prev_uv.0[1]],
-                    prev_uv.0,
-                    [prev_uv.0[0], prev_uv.1[1]],
-                ],
-            );
-            factor2 = factor;
+    texture: FaceTexture,
+) -> FaceTemplate {
+    let prev_uv = ([0., 0.], [1., 1.]);
+    let (positions, uvs, factor) = match s_type {
+        SquareType3D::Back(factor) => (
+            [
+                [-1., -1., 1. * factor],
+                [-1., 1., 1. * factor],
+                [1., 1., 1. * factor],
+                [1., -1., 1. * factor],
+            ],
+            [
+                prev_uv.1,
+                [prev_uv.1[0], prev_uv.0[1]],
+                prev_uv.0,
+                [prev_uv.0[0], prev_uv.1[1]],
+            ],
+            factor,
+        ),
+        SquareType3D::Right(factor) => (
+            [
+                [1. * factor, -1., -1.],
+                [1. * factor, -1., 1.],
+                [1. * factor, 1., 1.],
+                [1. * factor, 1., -1.],
+            ],
+            [
+                prev_uv.1,
+                [prev_uv.0[0], prev_uv.1[1]],
+                prev_uv.0,
+                [prev_uv.1[0], prev_uv.0[1]],
+            ],
+            factor,
+        ),
+        SquareType3D::Top(factor) => (
+            [
+                [-1., 1. * factor, -1.],
+                [1., 1. * factor, -1.],
+                [1., 1. * factor, 1.],
+                [-1., 1. * factor, 1.],
+            ],
+            [
+                prev_uv.1,
+                [prev_uv.0[0], prev_uv.1[1]],
+                prev_uv.0,
+                [prev_uv.1[0], prev_uv.0[1]],
+            ],
+            factor,
+        ),
+    };
+    let scale = Vec3::new(width, height, depth);
+    FaceTemplate {
+        positions: positions.map(|p| (Vec3::from_array(p) * scale).to_array()),
+        uvs,
+        indices: quad_indices(factor < 0.),
+        texture,
+    }
+}
+
+/// Face of the box `min..max` (in blocks, the block is `-0.5..0.5`) on `side`, scaled by `size`.
+/// Corners and UVs follow [`square_face`], UVs are cropped so the texture stays aligned to the block
+pub fn box_face(min: Vec3, max: Vec3, side: Side, size: f32, texture: FaceTexture) -> FaceTemplate {
+    let (axis, positive) = side.axis();
+    // Plane axes in `square_face` corner order
+    let ((a, b), corners) = match axis {
+        0 => ((1, 2), [(0, 0), (0, 1), (1, 1), (1, 0)]),
+        1 => ((0, 2), [(0, 0), (1, 0), (1, 1), (0, 1)]),
+        _ => ((0, 1), [(0, 0), (0, 1), (1, 1), (1, 0)]),
+    };
+    let bounds = [min, max];
+    let positions = corners.map(|(ca, cb)| {
+        let mut pos = Vec3::ZERO;
+        pos[axis] = bounds[positive as usize][axis];
+        pos[a] = bounds[ca][a];
+        pos[b] = bounds[cb][b];
+        pos
+    });
+    // u runs against the first texture axis and v against the second, like `square_face`
+    let (u_axis, v_axis) = match axis {
+        0 => (2, 1),
+        1 => (0, 2),
+        _ => (0, 1),
+    };
+    FaceTemplate {
+        positions: positions.map(|p| (p * size).to_array()),
+        uvs: positions.map(|p| [0.5 - p[u_axis], 0.5 - p[v_axis]]),
+        indices: quad_indices(!positive),
+        texture,
+    }
+}
+
+/// Two diagonal quads through the block, both windings so they are seen from every side
+pub fn cross_faces(size: f32, texture: FaceTexture) -> [FaceTemplate; 4] {
+    let h = 0.5;
+    let planes = [
+        [[-h, -h, -h], [-h, h, -h], [h, h, h], [h, -h, h]],
+        [[-h, -h, h], [-h, h, h], [h, h, -h], [h, -h, -h]],
+    ];
+    let uvs = [[1., 1.], [1., 0.], [0., 0.], [0., 1.]];
+    let face = |positions: [[f32; 3]; 4], negative| FaceTemplate {
+        positions: positions.map(|p| (Vec3::from_array(p) * size).to_array()),
+        uvs,
+        indices: quad_indices(negative),
+        texture,
+    };
+    [
+        face(planes[0], false),
+        face(planes[0], true),
+        face(planes[1], false),
+        face(planes[1], true),
+    ]
+}
+
+/// Indices of a quad, winding depends on which side it faces
+pub fn quad_indices(negative: bool) -> [u32; 6] {
+    if negative {
+        [0, 1, 3, 1, 2, 3]
+    } else {
+        [0, 3, 1, 1, 3, 2]
+    }
+}
+
+/// Vertex buffers of a chunk mesh, the [`Mesh`] is only built once at the end
+pub struct MeshBuffers<'a> {
+    mode: TextureMode,
+    /// Colors of biome tinted faces
+    climate: &'a ClimateMap,
+    positions: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    tiles: Vec<[f32; 4]>,
+    layers: Vec<u32>,
+    animations: Vec<[f32; 4]>,
+    colors: Vec<[f32; 4]>,
+    occlusions: Vec<f32>,
+    indices: Vec<u32>,
+}
+
+impl<'a> MeshBuffers<'a> {
+    pub fn new(mode: TextureMode, climate: &'a ClimateMap) -> Self {
+        Self::with_faces(0, mode, climate)
+    }
+    pub fn with_faces(faces: usize, mode: TextureMode, climate: &'a ClimateMap) -> Self {
+        let (tiles, layers) = match mode {
+            TextureMode::Atlas => (faces * 4, 0),
+            TextureMode::Array => (0, faces * 4),
+        };
+        Self {
+            mode,
+            climate,
+            positions: Vec::with_capacity(faces * 4),
+            uvs: Vec::with_capacity(faces * 4),
+            tiles: Vec::with_capacity(tiles),
+            layers: Vec::with_capacity(layers),
+            animations: Vec::with_capacity(faces * 4),
+            colors: Vec::with_capacity(faces * 4),
+            occlusions: Vec::with_capacity(faces * 4),
+            indices: Vec::with_capacity(faces * 6),
         }
-        SquareType3D::Right(factor) => {
-            mesh.insert_attribute(
-                Mesh::ATTRIBUTE_POSITION,
-                vec![
-                    [1. * factor, -1., -1.],
-                    [1. * factor, -1., 1.],
-                    [1. * factor, 1., 1.],
-                    [1. * factor, 1., -1.],
-                ],
-            );
-            mesh.insert_attribute(
-                Mesh::ATTRIBUTE_UV_0,
-                vec![
-                    prev_uv.1,
-                    [prev_uv.0[0], prev_uv.1[1]],
-                    prev_uv.0,
-                    [prev_uv.1[0], prev_uv.0[1]],
-                ],
-            );
-            factor2 = factor;
+    }
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+    /// Adds `face` of the block in `state` at `offset` from the section origin
+    pub fn push_face(&mut self, face: &FaceTemplate, offset: Vec3, state: BlockState) {
+        self.push_shaded_face(face, offset, state, NO_OCCLUSION);
+    }
+    /// Adds `face` with ambient occlusion, `occlusion` is 0 to 3 per corner, 3 is not occluded
+    pub fn push_shaded_face(
+        &mut self,
+        face: &FaceTemplate,
+        offset: Vec3,
+        state: BlockState,
+        occlusion: [u8; 4],
+    ) {
+        let tint = face.texture.tint.in_state(state);
+        let start = self.positions.len() as u32;
+        for pos in face.positions {
+            let pos = [pos[0] + offset.x, pos[1] + offset.y, pos[2] + offset.z];
+            self.positions.push(pos);
+            // Block corners are on whole blocks, the section origin is a block center
+            let (x, z) = (pos[0] / VOXEL_SIZE + 0.5, pos[2] / VOXEL_SIZE + 0.5);
+            self.colors.push(self.climate.color(tint, x, z));
         }
-        SquareType3D::Top(factor) => {
-            mesh.insert_attribute(
-                Mesh::ATTRIBUTE_POSITION,
-                vec![
-                    [-1., 1. * factor, -1.],
-                    [1., 1. * factor, -1.],
-                    [1., 1. * factor, 1.],
-                    [-1., 1. * factor, 1.],
-                ],
-            );
-            mesh.insert_attribute(
-                Mesh::ATTRIBUTE_UV_0,
-                vec![
-                    prev_uv.1,
-                    [prev_uv.0[0], prev_uv.1[1]],
-                    prev_uv.0,
-                    [prev_uv.1[0], prev_uv.0[1]],
-                ],
-            );
-            factor2 = factor;
+        self.uvs.extend_from_slice(&face.uvs);
+        match self.mode {
+            TextureMode::Atlas => self.tiles.extend_from_slice(&[face.texture.tile; 4]),
+            TextureMode::Array => self.layers.extend_from_slice(&[face.texture.layer; 4]),
         }
+        self.animations
+            .extend_from_slice(&[face.texture.animation; 4]);
+        self.occlusions
+            .extend(occlusion.map(|level| OCCLUSION_LIGHT[level as usize]));
+        // Quads are split along the brighter diagonal, else one dark corner shades half the quad
+        let [a, b, c, d] = occlusion.map(u32::from);
+        let shift = u32::from(a + c > b + d);
+        self.indices
+            .extend(face.indices.iter().map(|i| start + (i + shift) % 4));
     }
-    if factor2 < 0. {
-        mesh.insert_indices(Indices::U32(vec![0, 1, 3, 1, 2, 3]));
-    } else {
-        mesh.insert_indices(Indices::U32(vec![0, 3, 1, 1, 3, 2]));
+    /// Mesh with attrs:
+    /// [`UV_0`]
+    /// [`ATTRIBUTE_TILE`] or [`ATTRIBUTE_LAYER`]
+    /// [`ATTRIBUTE_ANIMATION`]
+    /// [`COLOR`]
+    /// [`ATTRIBUTE_OCCLUSION`]
+    /// [`Indices`]
+    /// [`POSITION`]
+    pub fn into_mesh(self) -> Mesh {
+        let mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+        .with_inserted_indices(Indices::U32(self.indices))
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+        .with_inserted_attribute(ATTRIBUTE_ANIMATION, self.animations)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, self.colors)
+        .with_inserted_attribute(ATTRIBUTE_OCCLUSION, self.occlusions);
+        match self.mode {
+            TextureMode::Atlas => mesh.with_inserted_attribute(ATTRIBUTE_TILE, self.tiles),
+            TextureMode::Array => mesh.with_inserted_attribute(ATTRIBUTE_LAYER, self.layers),
+        }
     }
-    mesh.scale_by(Vec3::new(width, height, depth));
-    mesh
 }
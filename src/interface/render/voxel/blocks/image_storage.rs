@@ -1,109 +1,385 @@
-use bevy::utils::HashMap;
+use bevy::{
+    render::{
+        render_asset::RenderAssetUsages,
+        render_resource::{
+            Extent3d, TextureDimension, TextureFormat, TextureViewDescriptor, TextureViewDimension,
+        },
+        texture::{
+            CompressedImageFormats, ImageAddressMode, ImageFilterMode, ImageSampler,
+            ImageSamplerDescriptor, ImageType,
+        },
+    },
+    utils::HashMap,
+};
 
-use crate::prelude::*;
+use crate::{
+    config::TextureMode,
+    interface::resources::{
+        blocks::ImageAnimation,
+        embedded::{UNKNOWN_TEXTURE_BYTES, UNKNOWN_TEXTURE_PATH},
+    },
+    prelude::*,
+    voxel::blocks::registry::UNKNOWN_BLOCK,
+};
 
+/// Border around each tile filled with its edge pixels,
+/// so nearest sampling at tile edges never reads a neighbour tile
+const PADDING: u32 = 2;
+const FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+const PIXEL_SIZE: usize = 4;
+
+/// Atlas of block textures. Source images are kept and the atlas is repacked
+/// from scratch whenever they change, tiles are looked up by image name.
+/// In [`TextureMode::Array`] a mipmapped texture array is built from them too
 pub struct BlockImageStorage {
+    mode: TextureMode,
+    /// Images by name in [`FORMAT`], `unknown` is kept apart and added on each repack
+    sources: HashMap<String, Image>,
+    unknown: Image,
     pub texture: Handle<Image>,
     pub texture_size: UVec2,
-    pub layout: Handle<TextureAtlasLayout>,
-    pub binds: HashMap<String, usize>,
+    /// Tile of each image in the atlas, without padding
+    rects: HashMap<String, Rect>,
+    /// Texture array, only in [`TextureMode::Array`]
+    pub array: Option<Handle<Image>>,
+    /// Layer of each image in [`Self::array`] (of the first frame), images are sorted by name
+    layers: HashMap<String, u32>,
+    animations: HashMap<String, ImageAnimation>,
 }
 
 impl BlockImageStorage {
-    pub fn get_texture_rect(&self, name: &str, layouts: &Res<Assets<TextureAtlasLayout>>) -> Rect {
-        let layout = layouts.get(self.layout.clone()).unwrap();
-        let ind = match self.binds.get(name) {
-            Some(i) => i,
-            None => {
-                return layout
-                    .textures
-                    .get(0 /*Default texture*/)
-                    .clone()
-                    .expect("Not found default texture")
-                    .clone();
-            }
+    /// Atlas of the `unknown` texture only
+    pub fn empty(asset_server: &AssetServer, mode: TextureMode) -> Self {
+        // Repacks can't wait for the asset, they decode the same bytes on the spot
+        let unknown = Image::from_buffer(
+            UNKNOWN_TEXTURE_BYTES,
+            ImageType::Extension("png"),
+            CompressedImageFormats::NONE,
+            true,
+            ImageSampler::Default,
+            RenderAssetUsages::default(),
+        )
+        .ok()
+        .and_then(|image| to_atlas_format(&image))
+        .expect("Unknown texture is a valid png");
+        let size = unknown.size();
+        let mut rects = HashMap::new();
+        rects.insert(
+            UNKNOWN_BLOCK.to_string(),
+            Rect::new(0., 0., size.x as f32, size.y as f32),
+        );
+        Self {
+            mode,
+            sources: HashMap::new(),
+            texture: asset_server.load("embedded://".to_string() + UNKNOWN_TEXTURE_PATH),
+            unknown,
+            texture_size: size,
+            rects,
+            array: None,
+            layers: HashMap::new(),
+            animations: HashMap::new(),
+        }
+    }
+
+    /// Tile of the image, the `unknown` one for missing names
+    pub fn get_texture_rect(&self, name: &str) -> Rect {
+        self.rects
+            .get(name)
+            .or_else(|| self.rects.get(UNKNOWN_BLOCK))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Layer of the image in the texture array, the `unknown` one for missing names
+    pub fn get_layer(&self, name: &str) -> u32 {
+        self.layers
+            .get(name)
+            .or_else(|| self.layers.get(UNKNOWN_BLOCK))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Frame count, seconds per frame, 1 to blend frames or 0, distance between frames.
+    /// The distance is in atlas V in [`TextureMode::Atlas`] and in layers in [`TextureMode::Array`]
+    pub fn get_animation(&self, name: &str) -> [f32; 4] {
+        let Some(animation) = self.animations.get(name) else {
+            return [1., 0., 0., 0.];
         };
-        match layout.textures.get(ind.clone()).clone() {
-            Some(rect) => rect.clone(),
-            None => {
-                return layout
-                    .textures
-                    .get(0 /*Default texture*/)
-                    .clone()
-                    .expect("Not found default texture")
-                    .clone();
+        let stride = match self.mode {
+            TextureMode::Atlas => {
+                let rect = self.get_texture_rect(name);
+                (rect.height() + (PADDING * 2) as f32) / self.texture_size.y as f32
             }
-        }
+            TextureMode::Array => 1.,
+        };
+        [
+            animation.frames as f32,
+            animation.frame_time,
+            animation.interpolate as u32 as f32,
+            stride,
+        ]
     }
-    pub fn merge(
+
+    /// Adds images, replacing ones with the same names, and repacks the atlas
+    pub fn insert<'a>(
         &mut self,
-        another: Self,
-        mut images: ResMut<Assets<Image>>,
-        layouts: &mut ResMut<Assets<TextureAtlasLayout>>,
+        images: impl IntoIterator<Item = (&'a String, &'a Image)>,
+        animations: &HashMap<String, ImageAnimation>,
+        assets: &mut Assets<Image>,
     ) {
-        let mut builder = TextureAtlasBuilder::default();
+        for (name, image) in images {
+            match to_atlas_format(image) {
+                Some(image) => {
+                    self.sources.insert(name.clone(), image);
+                }
+                None => error!(
+                    "Block image `{name}` has unsupported format {:?}",
+                    image.texture_descriptor.format
+                ),
+            }
+            match animations.get(name) {
+                Some(animation) => self.animations.insert(name.clone(), *animation),
+                None => self.animations.remove(name),
+            };
+        }
+        self.repack(assets);
+    }
 
-        builder.add_texture(
-            Some(self.texture.clone().into()),
-            images.get(self.texture.clone()).unwrap(),
-        );
-        builder.add_texture(
-            Some(another.texture.clone().into()),
-            images.get(another.texture.clone()).unwrap(),
+    /// Removes all images, the atlas is rebuilt by the next [`Self::insert`]
+    pub fn clear(&mut self) {
+        self.sources.clear();
+        self.animations.clear();
+    }
+
+    fn repack(&mut self, assets: &mut Assets<Image>) {
+        let mut tiles: Vec<(&str, &Image, u32)> = self
+            .sources
+            .iter()
+            .map(|(name, image)| {
+                let frames = self.animations.get(name).map_or(1, |a| a.frames);
+                (name.as_str(), image, frames)
+            })
+            .chain(std::iter::once((UNKNOWN_BLOCK, &self.unknown, 1)))
+            .collect();
+        // Frames of a strip are stacked in one tile, each with its own padding
+        let tile_size = |image: &Image, frames: u32| {
+            let frame = frame_size(image, frames);
+            UVec2::new(frame.x, frames * (frame.y + PADDING * 2) - PADDING * 2)
+        };
+        // Tallest first packs shelves tighter, names keep the layout stable
+        tiles.sort_by(|a, b| {
+            let (ha, hb) = (tile_size(a.1, a.2).y, tile_size(b.1, b.2).y);
+            hb.cmp(&ha).then(a.0.cmp(b.0))
+        });
+
+        let sizes: Vec<UVec2> = tiles
+            .iter()
+            .map(|(_, image, frames)| tile_size(image, *frames))
+            .collect();
+        let (size, corners) = pack(&sizes);
+        let mut data = vec![0; (size.x * size.y) as usize * PIXEL_SIZE];
+        let mut rects = HashMap::with_capacity(tiles.len());
+        for ((name, image, frames), corner) in tiles.iter().zip(corners) {
+            let frame_size = frame_size(image, *frames);
+            for index in 0..*frames {
+                let frame = Frame {
+                    image,
+                    index,
+                    size: frame_size,
+                };
+                let offset = UVec2::new(0, index * (frame_size.y + PADDING * 2));
+                blit(&mut data, size.x, &frame, corner + offset);
+            }
+            // First frame, the shader steps to the others
+            rects.insert(
+                name.to_string(),
+                Rect::from_corners(corner.as_vec2(), (corner + frame_size).as_vec2()),
+            );
+        }
+
+        let atlas = Image::new(
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            FORMAT,
+            RenderAssetUsages::default(),
         );
+        self.texture = assets.add(atlas);
+        self.texture_size = size;
+        self.rects = rects;
+
+        // Frames of a strip are consecutive layers
+        tiles.sort_by(|a, b| a.0.cmp(b.0));
+        let mut layers = HashMap::with_capacity(tiles.len());
+        let mut frames = Vec::new();
+        for (name, image, count) in &tiles {
+            layers.insert(name.to_string(), frames.len() as u32);
+            let size = frame_size(image, *count);
+            frames.extend((0..*count).map(|index| Frame { image, index, size }));
+        }
+        self.layers = layers;
+        if self.mode == TextureMode::Array {
+            self.array = Some(assets.add(texture_array(&frames)));
+        }
+    }
+}
+
+/// Frame `index` of a strip of frames from top to bottom, still images are a single frame
+struct Frame<'a> {
+    image: &'a Image,
+    index: u32,
+    size: UVec2,
+}
+
+impl Frame<'_> {
+    /// Pixel of the frame, coordinates are clamped to its edges
+    fn pixel(&self, pos: IVec2) -> &[u8] {
+        let pos = pos.clamp(IVec2::ZERO, self.size.as_ivec2() - 1).as_uvec2();
+        let row = self.index * self.size.y + pos.y;
+        let from = (row * self.size.x + pos.x) as usize * PIXEL_SIZE;
+        &self.image.data[from..from + PIXEL_SIZE]
+    }
+}
+
+fn frame_size(image: &Image, frames: u32) -> UVec2 {
+    let size = image.size();
+    UVec2::new(size.x, size.y / frames.max(1))
+}
 
-        let (mut layout, texture) = builder.finish().unwrap();
-        let mut self_layout = layouts.get(self.layout.clone()).unwrap().clone();
-        let mut another_layout = layouts.get(another.layout.clone()).unwrap().clone();
-        for rect in self_layout.textures.iter_mut() {
-            let rect2 = layout.textures.get(0).unwrap();
-            rect.min.x += rect2.min.x;
-            rect.min.y += rect2.min.y;
-            rect.max.x += rect2.min.x;
-            rect.max.y += rect2.min.y;
+/// Images stacked into array layers with full mip chains. Layers are as big as the biggest image,
+/// smaller ones are scaled up with nearest filtering
+fn texture_array(frames: &[Frame]) -> Image {
+    let size = frames
+        .iter()
+        .map(|frame| frame.size.max_element())
+        .max()
+        .unwrap_or(1)
+        .next_power_of_two();
+    let mip_levels = size.trailing_zeros() + 1;
+    // Layer major order: all mips of a layer, then the next layer
+    let mut data = Vec::new();
+    for frame in frames {
+        let mut mip = scaled(frame, size);
+        let mut mip_size = size;
+        data.extend_from_slice(&mip);
+        while mip_size > 1 {
+            mip = downsampled(&mip, mip_size);
+            mip_size /= 2;
+            data.extend_from_slice(&mip);
         }
-        for rect in another_layout.textures.iter_mut() {
-            let rect2 = layout.textures.get(1).unwrap();
-            rect.min.x += rect2.min.x;
-            rect.min.y += rect2.min.y;
-            rect.max.x += rect2.min.x;
-            rect.max.y += rect2.min.y;
+    }
+
+    let mut array = Image {
+        data,
+        asset_usage: RenderAssetUsages::default(),
+        ..default()
+    };
+    array.texture_descriptor.size = Extent3d {
+        width: size,
+        height: size,
+        depth_or_array_layers: frames.len() as u32,
+    };
+    array.texture_descriptor.dimension = TextureDimension::D2;
+    array.texture_descriptor.format = FORMAT;
+    array.texture_descriptor.mip_level_count = mip_levels;
+    array.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..default()
+    });
+    // Crisp close up, mips blend in the distance. UVs are in blocks, so textures repeat
+    array.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+        address_mode_u: ImageAddressMode::Repeat,
+        address_mode_v: ImageAddressMode::Repeat,
+        mag_filter: ImageFilterMode::Nearest,
+        min_filter: ImageFilterMode::Linear,
+        mipmap_filter: ImageFilterMode::Linear,
+        ..default()
+    });
+    array
+}
+
+/// Pixels of the frame scaled to `size`x`size` with nearest filtering
+fn scaled(frame: &Frame, size: u32) -> Vec<u8> {
+    let mut data = Vec::with_capacity((size * size) as usize * PIXEL_SIZE);
+    for y in 0..size {
+        for x in 0..size {
+            let source = UVec2::new(x, y) * frame.size / size;
+            data.extend_from_slice(frame.pixel(source.as_ivec2()));
         }
-        for (name, ind) in another.binds.iter() {
-            self.binds
-                .insert(name.clone(), ind + self_layout.textures.len());
+    }
+    data
+}
+
+/// Next mip level: each pixel is the average of a 2x2 block
+fn downsampled(mip: &[u8], size: u32) -> Vec<u8> {
+    let half = size / 2;
+    let mut data = Vec::with_capacity((half * half) as usize * PIXEL_SIZE);
+    for y in 0..half {
+        for x in 0..half {
+            for channel in 0..PIXEL_SIZE {
+                let sum: u32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .iter()
+                    .map(|(dx, dy)| {
+                        let index = ((y * 2 + dy) * size + x * 2 + dx) as usize * PIXEL_SIZE;
+                        mip[index + channel] as u32
+                    })
+                    .sum();
+                data.push((sum / 4) as u8);
+            }
         }
+    }
+    data
+}
+
+fn to_atlas_format(image: &Image) -> Option<Image> {
+    if image.texture_descriptor.format == FORMAT {
+        Some(image.clone())
+    } else {
+        image.convert(FORMAT)
+    }
+}
 
-        layout.textures = Vec::from(self_layout.textures);
-        layout.textures.append(&mut another_layout.textures);
+/// Packs padded tiles into rows, returns the atlas size and the corner of each tile
+fn pack(sizes: &[UVec2]) -> (UVec2, Vec<UVec2>) {
+    let cells: Vec<UVec2> = sizes
+        .iter()
+        .map(|size| *size + UVec2::splat(PADDING * 2))
+        .collect();
+    let area: u32 = cells.iter().map(|cell| cell.x * cell.y).sum();
+    let widest = cells.iter().map(|cell| cell.x).max().unwrap_or(1);
+    let width = ((area as f32).sqrt().ceil() as u32)
+        .max(widest)
+        .next_power_of_two();
 
-        self.texture_size = texture.size();
-        self.texture = images.add(texture);
-        *layouts.get_mut(self.layout.clone()).unwrap() = layout;
-        //*images.get_mut(self.texture.clone()).unwrap() = texture;
+    let mut corners = Vec::with_capacity(cells.len());
+    let (mut x, mut y, mut row_height) = (0, 0, 0);
+    for cell in &cells {
+        if x + cell.x > width {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+        corners.push(UVec2::new(x, y) + PADDING);
+        x += cell.x;
+        row_height = row_height.max(cell.y);
     }
-    pub fn empty(
-        asset_server: Res<AssetServer>,
-        layouts: &mut ResMut<Assets<TextureAtlasLayout>>,
-    ) -> Self {
-        let mut binds = HashMap::new();
-        binds.insert("unknown".to_string(), 0);
-        let mut layout = TextureAtlasLayout::new_empty(Vec2::new(
-            crate::interface::resources::embedded::UNKNOWN_TEXTURE_SIZE.0 as f32,
-            crate::interface::resources::embedded::UNKNOWN_TEXTURE_SIZE.1 as f32,
-        ));
-        layout.add_texture(Rect {
-            min: Vec2::new(0., 0.),
-            max: Vec2::new(16., 16.),
-        });
-        Self {
-            texture: asset_server.load(
-                "embedded://".to_string()
-                    + crate::interface::resources::embedded::UNKNOWN_TEXTURE_PATH,
-            ),
-            texture_size: UVec2::from(crate::interface::resources::embedded::UNKNOWN_TEXTURE_SIZE),
-            layout: layouts.add(layout),
-            binds,
+    (UVec2::new(width, y + row_height), corners)
+}
+
+/// Copies the frame to `corner` of the atlas, repeating its edge pixels over the padding
+fn blit(atlas: &mut [u8], atlas_width: u32, frame: &Frame, corner: UVec2) {
+    let size = frame.size.as_ivec2();
+    let corner = corner.as_ivec2();
+    let padding = PADDING as i32;
+    for y in -padding..size.y + padding {
+        for x in -padding..size.x + padding {
+            let target = corner + IVec2::new(x, y);
+            let to = (target.y as usize * atlas_width as usize + target.x as usize) * PIXEL_SIZE;
+            atlas[to..to + PIXEL_SIZE].copy_from_slice(frame.pixel(IVec2::new(x, y)));
         }
     }
 }
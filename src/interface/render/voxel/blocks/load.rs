@@ -1,7 +1,17 @@
 use super::*;
-use crate::prelude::*;
+use crate::{
+    config::GameSettings,
+    interface::{render::voxel::chunk::RenderOfChunk, resources::blocks::BlockTypesAsset},
+    prelude::*,
+    voxel::{blocks::registry::BlockRegistry, chunks::region::WorldDir},
+};
+use bevy::{
+    asset::{LoadState, LoadedFolder},
+    utils::{thiserror, HashMap},
+};
 use iyes_progress::ProgressSystem;
 use storage::BlockStorage;
+use thiserror::Error;
 
 pub struct BlockLoadPlugin;
 
@@ -13,6 +23,10 @@ impl Plugin for BlockLoadPlugin {
                 .track_progress()
                 .run_if(in_state(GameState::Load)),
         )
+        .add_systems(
+            Update,
+            reload_block_types.run_if(in_state(GameState::Menu).or_else(in_state(GameState::Play))),
+        )
         .add_systems(Startup, create_blocks_storage)
         .add_systems(OnEnter(GameState::Load), load_block_types);
     }
@@ -20,33 +34,261 @@ impl Plugin for BlockLoadPlugin {
 
 pub fn create_blocks_storage(
     asset_server: Res<AssetServer>,
-    layouts: ResMut<Assets<TextureAtlasLayout>>,
+    settings: Res<GameSettings>,
     mut commands: Commands,
 ) {
-    commands.insert_resource(BlockStorage::new(asset_server, layouts));
+    commands.insert_resource(BlockStorage::new(asset_server, settings.graphics.textures));
 }
 
+/// Directory with the built-in packs
+const PACKS_DIR: &str = "asset://blocks_types";
+
+/// Folders with block packs (`*.btypes.ron`), the built-in one and [`GameSettings::block_packs`]
 #[derive(Resource)]
-pub struct BlockTypesFile {
-    types_file: Handle<crate::interface::resources::blocks::BlockTypesAsset>,
+pub struct BlockPacks {
+    folders: Vec<Handle<LoadedFolder>>,
+    /// Packs are merged into the storage once all of them are loaded
+    added: bool,
+}
+
+impl BlockPacks {
+    /// Pack files sorted by path, so merging doesn't depend on load order.
+    /// `None` while some folder isn't read yet, failed folders have no files
+    fn files(
+        &self,
+        folders: &Assets<LoadedFolder>,
+        asset_server: &AssetServer,
+    ) -> Option<Vec<Handle<BlockTypesAsset>>> {
+        let mut files = Vec::new();
+        for handle in &self.folders {
+            let Some(folder) = folders.get(handle) else {
+                if folder_failed(handle, asset_server) {
+                    continue;
+                }
+                return None;
+            };
+            files.extend(
+                folder
+                    .handles
+                    .iter()
+                    .filter_map(|handle| handle.clone().try_typed::<BlockTypesAsset>().ok()),
+            );
+        }
+        files.sort_by_key(pack_path);
+        Some(files)
+    }
+    fn is_pack(
+        &self,
+        id: AssetId<BlockTypesAsset>,
+        folders: &Assets<LoadedFolder>,
+        asset_server: &AssetServer,
+    ) -> bool {
+        self.files(folders, asset_server)
+            .is_some_and(|files| files.iter().any(|file| file.id() == id))
+    }
+}
+
+/// Missing or mistyped folder, or a platform without directory listing (web)
+fn folder_failed(folder: &Handle<LoadedFolder>, asset_server: &AssetServer) -> bool {
+    matches!(asset_server.load_state(folder), LoadState::Failed)
+}
+
+fn pack_path(handle: &Handle<BlockTypesAsset>) -> String {
+    handle
+        .path()
+        .map(|path| path.to_string())
+        .unwrap_or_default()
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum BlockPackError {
+    #[error("Block type `{name}` is defined in both `{first}` and `{second}`")]
+    TypeConflict {
+        name: String,
+        first: String,
+        second: String,
+    },
+    #[error("{file}: block type `{name}` references image `{image}` of a pack that isn't loaded, `unknown` is used")]
+    UnknownImage {
+        name: String,
+        image: String,
+        file: String,
+    },
+    #[error("Block pack folder `{0}` could not be loaded, it is skipped")]
+    FolderFailed(String),
+    #[error("Block image `{name}` is defined in both `{first}` and `{second}`")]
+    ImageConflict {
+        name: String,
+        first: String,
+        second: String,
+    },
+}
+
+/// Merges packs into one, a name defined twice is reported and the first definition is kept
+fn merge_packs(
+    files: &[Handle<BlockTypesAsset>],
+    assets: &Assets<BlockTypesAsset>,
+) -> BlockTypesAsset {
+    let mut merged = BlockTypesAsset::default();
+    let mut type_files = HashMap::<String, String>::new();
+    let mut image_files = HashMap::<String, String>::new();
+    for file in files {
+        let Some(pack) = assets.get(file) else {
+            continue;
+        };
+        let path = pack_path(file);
+        for warning in &pack.warnings {
+            warn!("{warning}");
+        }
+        for (name, image) in &pack.images {
+            if let Some(first) = image_files.get(name) {
+                error!(
+                    "{}",
+                    BlockPackError::ImageConflict {
+                        name: name.clone(),
+                        first: first.clone(),
+                        second: path.clone(),
+                    }
+                );
+                continue;
+            }
+            image_files.insert(name.clone(), path.clone());
+            merged.images.insert(name.clone(), image.clone());
+            if let Some(animation) = pack.animations.get(name) {
+                merged.animations.insert(name.clone(), *animation);
+            }
+        }
+        for (name, type_) in &pack.types {
+            if let Some(first) = type_files.get(name) {
+                error!(
+                    "{}",
+                    BlockPackError::TypeConflict {
+                        name: name.clone(),
+                        first: first.clone(),
+                        second: path.clone(),
+                    }
+                );
+                continue;
+            }
+            type_files.insert(name.clone(), path.clone());
+            merged.types.insert(name.clone(), type_.clone());
+        }
+    }
+    // References inside a pack are checked by its loader, these are the ones to other packs
+    for (name, type_) in &merged.types {
+        for image in type_.images() {
+            if !merged.images.contains_key(image) {
+                error!(
+                    "{}",
+                    BlockPackError::UnknownImage {
+                        name: name.clone(),
+                        image: image.to_string(),
+                        file: type_files[name].clone(),
+                    }
+                );
+            }
+        }
+    }
+    merged
 }
 
+/// Progress is a step per pack folder and per pack file, failed folders and packs count as done
 fn check_for_load(
-    types: ResMut<BlockTypesFile>,
+    mut packs: ResMut<BlockPacks>,
     mut storage: ResMut<BlockStorage>,
-    assets: Res<Assets<crate::interface::resources::blocks::BlockTypesAsset>>,
-    images: ResMut<Assets<Image>>,
-    layouts: ResMut<Assets<TextureAtlasLayout>>,
+    folders: Res<Assets<LoadedFolder>>,
+    assets: Res<Assets<BlockTypesAsset>>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
 ) -> iyes_progress::Progress {
-    if let Some(asset) = assets.get(types.types_file.clone()) {
-        storage.add_block_types(asset, images, layouts);
-        return true.into();
+    let read_folders = packs
+        .folders
+        .iter()
+        .filter(|folder| folders.contains(*folder) || folder_failed(folder, &asset_server))
+        .count() as u32;
+    let folders_total = packs.folders.len() as u32;
+    let Some(files) = packs.files(&folders, &asset_server) else {
+        return iyes_progress::Progress {
+            done: read_folders,
+            total: folders_total,
+        };
+    };
+    let loaded = files
+        .iter()
+        .filter(|file| {
+            assets.contains(*file) || matches!(asset_server.load_state(*file), LoadState::Failed)
+        })
+        .count() as u32;
+    let progress = iyes_progress::Progress {
+        done: read_folders + loaded,
+        total: folders_total + files.len() as u32,
+    };
+    if loaded == files.len() as u32 && !packs.added {
+        for folder in &packs.folders {
+            if folder_failed(folder, &asset_server) {
+                let path = folder
+                    .path()
+                    .map(|path| path.to_string())
+                    .unwrap_or_default();
+                error!("{}", BlockPackError::FolderFailed(path));
+            }
+        }
+        storage.add_block_types(&merge_packs(&files, &assets), &mut images);
+        packs.added = true;
     }
-    return false.into();
+    progress
 }
 
-fn load_block_types(asset_server: Res<AssetServer>, mut commands: Commands) {
-    commands.insert_resource(BlockTypesFile {
-        types_file: asset_server.load("asset://blocks_types/group.btypes.ron"),
+fn load_block_types(
+    asset_server: Res<AssetServer>,
+    settings: Res<GameSettings>,
+    mut commands: Commands,
+) {
+    let folders = std::iter::once(PACKS_DIR)
+        .chain(settings.block_packs.iter().map(String::as_str))
+        .map(|dir| asset_server.load_folder(dir.to_string()))
+        .collect();
+    commands.insert_resource(BlockPacks {
+        folders,
+        added: false,
     });
 }
+
+/// Hot-reload of the packs, their images are loader dependencies so their edits reload them too
+#[allow(clippy::too_many_arguments)]
+fn reload_block_types(
+    mut events: EventReader<AssetEvent<BlockTypesAsset>>,
+    packs: Res<BlockPacks>,
+    folders: Res<Assets<LoadedFolder>>,
+    assets: Res<Assets<BlockTypesAsset>>,
+    mut storage: ResMut<BlockStorage>,
+    mut images: ResMut<Assets<Image>>,
+    registry: Option<ResMut<BlockRegistry>>,
+    world: Option<Res<WorldDir>>,
+    mut renders: Query<&mut RenderOfChunk>,
+    asset_server: Res<AssetServer>,
+) {
+    let modified = events.read().any(|event| match event {
+        AssetEvent::Modified { id } => packs.is_pack(*id, &folders, &asset_server),
+        _ => false,
+    });
+    if !modified {
+        return;
+    }
+    let Some(files) = packs.files(&folders, &asset_server) else {
+        return;
+    };
+    info!("Reloading block types");
+    storage.reload_block_types(&merge_packs(&files, &assets), &mut images);
+    // New types get world ids, known ones are bound already
+    if let (Some(mut registry), Some(world)) = (registry, world) {
+        storage.bind_registry(&mut registry);
+        if let Err(err) = registry.save(&world.0) {
+            error!("Failed to save block registry: {err}");
+        }
+    }
+    for mut render in renders.iter_mut() {
+        render.mark_all_dirty();
+    }
+}
@@ -1,3 +1,3 @@
-mod image_storage;
+pub mod image_storage;
 pub mod load;
 pub mod storage;
@@ -1,13 +1,35 @@
+use std::sync::Arc;
+
 use bevy::utils::HashMap;
 
 use crate::{
+    config::TextureMode,
     interface::resources::blocks::{BlockTypesAsset, UnMeshedBlockType},
     prelude::*,
-    voxel::blocks::BlockId,
+    voxel::blocks::{
+        registry::{BlockRegistry, UNKNOWN_BLOCK},
+        Block, BlockId, BlockProperties, Side, Transparency,
+    },
 };
 
+#[derive(Clone)]
 pub struct BlockType {
+    /// Full faces of a cube, textures of these are also used by models
     pub sides: BlockSides,
+    /// `None` for full cubes
+    pub model: Option<Vec<ModelFace>>,
+    /// [`Side::bit`]s of sides fully covered by the block, only these hide neighbour faces
+    pub full_sides: u8,
+    pub properties: BlockProperties,
+}
+
+/// Face of a non-cube model
+#[derive(Clone)]
+pub struct ModelFace {
+    pub face: FaceTemplate,
+    /// Side of the block the face lies on, it's hidden like a cube face on that side.
+    /// `None` for faces inside the block, they are always drawn
+    pub cull: Option<Side>,
 }
 
 #[derive(Clone)]
@@ -20,27 +42,165 @@ pub struct BlockSides {
     pub back: BlockSideInfo,
 }
 
+impl BlockSides {
+    pub fn get(&self, side: Side) -> &BlockSideInfo {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+            Side::Bottom => &self.bottom,
+            Side::Top => &self.top,
+            Side::Forward => &self.forward,
+            Side::Back => &self.back,
+        }
+    }
+}
+
 #[derive(Clone)]
-pub struct BlockSideInfo(pub Mesh);
+pub struct BlockSideInfo(pub FaceTemplate);
 
-use crate::interface::{render::mesh::meshing_block_type, resources::blocks::BlockFaces};
+use crate::interface::{
+    render::{mesh::meshing_block_type, util::FaceTemplate},
+    resources::blocks::{BlockFace, BlockFaces},
+};
 
 use super::image_storage::BlockImageStorage;
 
+/// Meshed block types, shared with meshing tasks
+#[derive(Clone, Default)]
+pub struct BlockTypes {
+    types: HashMap<BlockId, BlockType>,
+    unknown: BlockId,
+    /// Vertex format of meshes follows the texture mode
+    pub texture_mode: TextureMode,
+}
+impl BlockTypes {
+    pub fn get(&self, id: BlockId) -> Option<&BlockType> {
+        self.types.get(&id)
+    }
+    pub fn get_or_default(&self, id: BlockId) -> &BlockType {
+        match self.get(id) {
+            Some(t) => t,
+            None => self.get(self.unknown).unwrap(),
+        }
+    }
+    pub fn properties(&self, id: BlockId) -> BlockProperties {
+        self.get_or_default(id).properties
+    }
+    /// `None` for air
+    pub fn transparency(&self, block: Block) -> Option<Transparency> {
+        match block {
+            Block::Air => None,
+            Block::Solid(id, _) => Some(self.properties(id).transparency),
+        }
+    }
+    pub fn is_opaque(&self, block: Block) -> bool {
+        self.transparency(block) == Some(Transparency::Opaque)
+    }
+    /// Opaque full cube, its faces can be culled with bit masks
+    pub fn is_opaque_cube(&self, block: Block) -> bool {
+        match block {
+            Block::Air => false,
+            Block::Solid(id, _) => self.is_opaque(block) && self.get_or_default(id).model.is_none(),
+        }
+    }
+    /// Rotation of the block model from its state, `None` when it isn't turned
+    pub fn rotation(&self, block: Block) -> Option<Mat3> {
+        match block {
+            Block::Air => None,
+            Block::Solid(id, state) => state.rotation(self.properties(id).states),
+        }
+    }
+    /// `side` of the block is fully covered
+    pub fn is_full(&self, block: Block, side: Side) -> bool {
+        let Block::Solid(id, _) = block else {
+            return false;
+        };
+        // Side of the model that was turned to `side`
+        let side = match self.rotation(block) {
+            Some(m) => side.transformed(m.transpose()),
+            None => side,
+        };
+        self.get_or_default(id).full_sides & side.bit() != 0
+    }
+    /// Block hides the faces of the neighbour on its `side`
+    pub fn occludes(&self, block: Block, side: Side) -> bool {
+        self.is_opaque(block) && self.is_full(block, side)
+    }
+    /// Face of `block` on its `side`, touching `neighbour`, must be drawn.
+    /// Only full neighbour sides hide it: opaque ones always, same translucent blocks merge
+    /// (no faces inside glass), cutout blocks keep faces between each other
+    pub fn face_visible(&self, block: Block, neighbour: Block, side: Side) -> bool {
+        if !self.is_full(neighbour, side.opposite()) {
+            return true;
+        }
+        match self.transparency(neighbour) {
+            None => true,
+            Some(Transparency::Opaque) => false,
+            Some(Transparency::Cutout) => true,
+            Some(Transparency::Translucent) => block.id() != neighbour.id(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl BlockTypes {
+    /// Opaque cubes for meshing tests, side `side` of `id` has texture layer `id * 6 + side`.
+    /// The first id is the unknown type
+    pub fn test_cubes(ids: &[BlockId], texture_mode: TextureMode) -> Self {
+        use crate::interface::{
+            constants::VOXEL_SIZE,
+            render::util::{square_face, FaceTexture, SquareType3D},
+        };
+        let types = ids.iter().map(|id| {
+            let side = |side: Side, s_type| {
+                let texture = FaceTexture {
+                    layer: id.0 * 6 + side as u32,
+                    ..default()
+                };
+                let h = VOXEL_SIZE / 2.;
+                BlockSideInfo(square_face(h, h, h, s_type, texture))
+            };
+            let type_ = BlockType {
+                sides: BlockSides {
+                    left: side(Side::Left, SquareType3D::Right(-1.)),
+                    right: side(Side::Right, SquareType3D::Right(1.)),
+                    top: side(Side::Top, SquareType3D::Top(1.)),
+                    bottom: side(Side::Bottom, SquareType3D::Top(-1.)),
+                    forward: side(Side::Forward, SquareType3D::Back(-1.)),
+                    back: side(Side::Back, SquareType3D::Back(1.)),
+                },
+                model: None,
+                full_sides: 0b11_1111,
+                properties: BlockProperties::default(),
+            };
+            (*id, type_)
+        });
+        Self {
+            types: types.collect(),
+            unknown: ids[0],
+            texture_mode,
+        }
+    }
+    pub fn with_properties(mut self, id: BlockId, properties: BlockProperties) -> Self {
+        self.types.get_mut(&id).unwrap().properties = properties;
+        self
+    }
+}
+
 #[derive(Resource)]
 pub struct BlockStorage {
     un_meshed_storage: HashMap<BlockId, UnMeshedBlockType>,
-    storage: HashMap<BlockId, BlockType>,
+    storage: Arc<BlockTypes>,
     name_binds: HashMap<String, BlockId>,
     last_id: BlockId,
     pub(crate) imgs: BlockImageStorage,
 }
 impl BlockStorage {
-    pub fn new(
-        asset_server: Res<AssetServer>,
-        mut layouts: ResMut<Assets<TextureAtlasLayout>>,
-    ) -> Self {
-        let storage = HashMap::new();
+    pub fn new(asset_server: Res<AssetServer>, texture_mode: TextureMode) -> Self {
+        let storage = Arc::new(BlockTypes {
+            texture_mode,
+            ..default()
+        });
         let name_binds = HashMap::new();
 
         let mut self_ = Self {
@@ -48,90 +208,119 @@ impl BlockStorage {
             un_meshed_storage: HashMap::new(),
             name_binds,
             last_id: BlockId(1),
-            imgs: BlockImageStorage::empty(asset_server, &mut layouts),
+            imgs: BlockImageStorage::empty(&asset_server, texture_mode),
         };
         self_.add(
-            "unknown".to_string(),
+            UNKNOWN_BLOCK.to_string(),
             UnMeshedBlockType::Block {
-                faces: BlockFaces {
-                    top: "unknown".to_string(),
-                    bottom: "unknown".to_string(),
-                    left: "unknown".to_string(),
-                    right: "unknown".to_string(),
-                    forward: "unknown".to_string(),
-                    backward: "unknown".to_string(),
-                },
+                faces: BlockFaces::all(BlockFace::untinted(UNKNOWN_BLOCK)),
+                properties: BlockProperties::default(),
             },
-            &layouts.into(),
         );
+        Arc::make_mut(&mut self_.storage).unknown =
+            *self_.get_id_by_name(UNKNOWN_BLOCK.to_string()).unwrap();
 
         self_
     }
-    pub fn add(
-        &mut self,
-        name: String,
-        type_: UnMeshedBlockType,
-        layouts: &Res<Assets<TextureAtlasLayout>>,
-    ) {
-        self.add_block_type(name.clone(), meshing_block_type(self, &type_, &layouts));
+    pub fn add(&mut self, name: String, type_: UnMeshedBlockType) {
+        self.add_block_type(name.clone(), meshing_block_type(self, &type_));
         self.un_meshed_storage
             .insert(self.get_id_by_name(name).unwrap().clone(), type_);
     }
 
     fn add_block_type(&mut self, name: String, t: BlockType) {
-        self.storage.insert(self.last_id, t);
+        Arc::make_mut(&mut self.storage)
+            .types
+            .insert(self.last_id, t);
         self.name_binds.insert(name, self.last_id);
         self.last_id.0 += 1;
     }
 
-    pub fn add_block_types(
-        &mut self,
-        asset: &BlockTypesAsset,
-        mut images: ResMut<Assets<Image>>,
-        mut layouts: ResMut<Assets<TextureAtlasLayout>>,
-    ) {
-        let mut builder = TextureAtlasBuilder::default();
-        let mut binds = HashMap::<String, usize>::new();
-        let mut i = 0;
-        for (name, img) in &asset.images {
-            builder.add_texture(None, img);
-            binds.insert(name.clone(), i);
-            i += 1;
+    pub fn add_block_types(&mut self, asset: &BlockTypesAsset, images: &mut Assets<Image>) {
+        self.imgs.insert(&asset.images, &asset.animations, images);
+        self.update_meshes();
+        for (name, type_) in sorted_types(asset) {
+            self.add(name.clone(), type_.clone());
         }
-        let (layout, texture) = builder.finish().unwrap();
-        let new_img_storage = BlockImageStorage {
-            texture_size: texture.size(),
-            texture: images.add(texture),
-            layout: layouts.add(layout),
-            binds,
-        };
-        self.imgs.merge(new_img_storage, images, &mut layouts);
-        let layout_unmut = Res::from(layouts);
-        self.update_meshes(&layout_unmut);
-        for (name, type_) in asset.types.iter() {
-            self.add(name.clone(), type_.clone(), &layout_unmut);
+    }
+
+    /// Rebuilds the atlas and meshes from a modified asset.
+    /// Known names keep their ids, new ones are appended and types removed from the file stay until restart
+    pub fn reload_block_types(&mut self, asset: &BlockTypesAsset, images: &mut Assets<Image>) {
+        self.imgs.clear();
+        self.imgs.insert(&asset.images, &asset.animations, images);
+        for (name, type_) in sorted_types(asset) {
+            match self.get_id_by_name(name.clone()) {
+                Some(id) => {
+                    self.un_meshed_storage.insert(*id, type_.clone());
+                }
+                None => self.add(name.clone(), type_.clone()),
+            }
         }
+        self.update_meshes();
     }
 
-    pub fn update_meshes(&mut self, layouts: &Res<Assets<TextureAtlasLayout>>) {
+    /// Renumbers block types to the world ids, names new to the world are appended to the registry.
+    /// Ids of names the registry has but the storage doesn't are rendered as `unknown`
+    pub fn bind_registry(&mut self, registry: &mut BlockRegistry) {
+        let mut names: Vec<(String, BlockId)> = self
+            .name_binds
+            .iter()
+            .map(|(name, id)| (name.clone(), *id))
+            .collect();
+        names.sort_by(|a, b| a.0.cmp(&b.0));
+        let remap: HashMap<BlockId, BlockId> = names
+            .iter()
+            .map(|(name, id)| (*id, registry.get_or_insert(name)))
+            .collect();
+
+        let storage = Arc::make_mut(&mut self.storage);
+        storage.types = storage
+            .types
+            .drain()
+            .map(|(id, type_)| (remap[&id], type_))
+            .collect();
+        storage.unknown = remap[&storage.unknown];
+        self.un_meshed_storage = self
+            .un_meshed_storage
+            .drain()
+            .map(|(id, type_)| (remap[&id], type_))
+            .collect();
+        for id in self.name_binds.values_mut() {
+            *id = remap[id];
+        }
+        self.last_id = registry.next_id();
+    }
+
+    pub fn update_meshes(&mut self) {
+        let mut storage = (*self.storage).clone();
         for (id, type_) in self.un_meshed_storage.iter() {
-            let type_ = meshing_block_type(self, type_, layouts);
-            *self.storage.get_mut(id).unwrap() = type_;
+            let type_ = meshing_block_type(self, type_);
+            *storage.types.get_mut(id).unwrap() = type_;
         }
+        self.storage = Arc::new(storage);
     }
 
     pub fn get_id_by_name(&self, name: String) -> Option<&BlockId> {
         self.name_binds.get(&name)
     }
-    pub fn get(&self, id: BlockId) -> Option<&BlockType> {
-        self.storage.get(&id)
+    /// Id of the type drawn for ids without a type
+    pub fn unknown_id(&self) -> BlockId {
+        self.storage.unknown
     }
-    pub fn get_or_default(&self, id: BlockId) -> &BlockType {
-        match self.get(id) {
-            Some(t) => t,
-            None => self
-                .get(self.get_id_by_name("unknown".to_string()).unwrap().clone())
-                .unwrap(),
-        }
+    /// Properties of the type, `unknown` ones for unknown ids
+    pub fn properties(&self, id: BlockId) -> BlockProperties {
+        self.storage.properties(id)
     }
+    /// Cheap shared handle to meshed types for use off the main thread
+    pub fn types(&self) -> Arc<BlockTypes> {
+        self.storage.clone()
+    }
+}
+
+/// Sorted so ids don't depend on the map order
+fn sorted_types(asset: &BlockTypesAsset) -> Vec<(&String, &UnMeshedBlockType)> {
+    let mut types: Vec<_> = asset.types.iter().collect();
+    types.sort_by(|a, b| a.0.cmp(b.0));
+    types
 }
@@ -1,286 +1,903 @@
-use bevy::ecs::query::QueryFilter;
-use primitive_types::U256;
+use std::sync::{Arc, OnceLock};
+
+use bevy::{
+    ecs::query::QueryFilter,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+};
 
 use crate::{
-    interface::{constants::VOXEL_SIZE, render::util::void_mesh},
+    config::{GameSettings, MeshingMode, TextureMode},
+    interface::{
+        constants::VOXEL_SIZE,
+        render::{
+            tint::ClimateMap,
+            util::{FaceTemplate, MeshBuffers, NO_OCCLUSION},
+        },
+    },
     prelude::*,
     voxel::{
-        blocks::{Block, BlockId},
-        chunks::chunk::Chunk,
+        blocks::{Block, Side, Transparency},
+        chunks::{
+            chunk::Chunk,
+            map::{update_chunk_map, ChunkMap},
+        },
+        generator::WorldGen,
     },
 };
 
-use super::{blocks::storage::BlockStorage, VoxelMaterial};
+use super::{
+    blocks::storage::{BlockStorage, BlockTypes},
+    greedy::create_greedy_chunk_mesh,
+    stream::stream_chunks,
+    VoxelMaterial,
+};
 
 pub struct ChunkRenderPlugin;
 
 impl Plugin for ChunkRenderPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, make_meshes.run_if(in_state(GameState::Play)));
+        app.add_systems(
+            Update,
+            (
+                remesh_all.run_if(resource_changed::<GameSettings>),
+                make_meshes,
+                apply_meshes,
+            )
+                .chain()
+                // Sections of chunks unloaded this frame must be gone before meshes are set
+                .after(stream_chunks)
+                .run_if(in_state(GameState::Play)),
+        )
+        .add_systems(PreUpdate, link_neighbours.after(update_chunk_map));
     }
 }
 
 #[derive(Component)]
 pub struct RenderOfChunk {
-    pub is_generated_mesh: bool,
+    /// Bit per section which mesh must be rebuilt
+    pub dirty_sections: u32,
+    /// Child entities with section meshes, `None` for empty sections
+    pub sections: [Option<Entity>; SECTIONS],
     pub left_chunk: Option<Entity>,
     pub right_chunk: Option<Entity>,
     pub forward_chunk: Option<Entity>,
     pub backward_chunk: Option<Entity>,
+    /// Climate of the chunk columns never changes, it's sampled once by a mesh task
+    climate: Arc<OnceLock<ClimateMap>>,
+}
+impl Default for RenderOfChunk {
+    fn default() -> Self {
+        Self {
+            dirty_sections: ALL_SECTIONS,
+            sections: [None; SECTIONS],
+            left_chunk: None,
+            right_chunk: None,
+            forward_chunk: None,
+            backward_chunk: None,
+            climate: default(),
+        }
+    }
 }
+
+const ALL_SECTIONS: u32 = u32::MAX >> (u32::BITS as usize - SECTIONS);
+
 impl RenderOfChunk {
-    pub fn get<T: QueryFilter>(
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty_sections = ALL_SECTIONS;
+    }
+    /// Marks section of block at height `y` and the section touching it, if `y` is on its border
+    pub fn mark_dirty(&mut self, y: i32) {
+        if y < 0 || y >= CHUNK_H as i32 {
+            return;
+        }
+        let (section, local) = (y as usize / SECTION_H, y as usize % SECTION_H);
+        self.dirty_sections |= 1 << section;
+        if local == 0 && section > 0 {
+            self.dirty_sections |= 1 << (section - 1);
+        }
+        if local == SECTION_H - 1 && section + 1 < SECTIONS {
+            self.dirty_sections |= 1 << (section + 1);
+        }
+    }
+    pub fn is_dirty(&self, section: usize) -> bool {
+        self.dirty_sections & (1 << section) != 0
+    }
+    /// Neighbours whose border faces depend on the block at `local`
+    pub fn border_neighbours(&self, local: IVec3) -> impl Iterator<Item = Entity> {
+        [
+            (local.x == 0, self.left_chunk),
+            (local.x == CHUNK_W as i32 - 1, self.right_chunk),
+            (local.z == 0, self.backward_chunk),
+            (local.z == CHUNK_D as i32 - 1, self.forward_chunk),
+        ]
+        .into_iter()
+        .filter_map(|(on_border, e)| if on_border { e } else { None })
+    }
+    /// Neighbour across the X border at `local` and whether `local` is on the forward Z border,
+    /// the link of that neighbour on the same Z side is the diagonal chunk sharing the corner
+    pub fn corner_neighbour(&self, local: IVec3) -> Option<(Entity, bool)> {
+        let x_neighbour = if local.x == 0 {
+            self.left_chunk
+        } else if local.x == CHUNK_W as i32 - 1 {
+            self.right_chunk
+        } else {
+            None
+        }?;
+        if local.z == 0 {
+            Some((x_neighbour, false))
+        } else if local.z == CHUNK_D as i32 - 1 {
+            Some((x_neighbour, true))
+        } else {
+            None
+        }
+    }
+    pub fn z_link(&self, forward: bool) -> Option<Entity> {
+        match forward {
+            true => self.forward_chunk,
+            false => self.backward_chunk,
+        }
+    }
+    /// Left-backward, right-backward, left-forward and right-forward chunks,
+    /// through the links of the left and right neighbours
+    pub fn diagonals<'a>(
+        &self,
+        renders: impl Fn(Entity) -> Option<&'a RenderOfChunk>,
+    ) -> [Option<Entity>; 4] {
+        let via = |side: Option<Entity>, forward| renders(side?)?.z_link(forward);
+        [
+            via(self.left_chunk, false),
+            via(self.right_chunk, false),
+            via(self.left_chunk, true),
+            via(self.right_chunk, true),
+        ]
+    }
+    /// Copies the chunk and the borders of its neighbours for meshing off the main thread
+    pub fn snapshot<T: QueryFilter>(
         &self,
-        x: i32,
-        y: i32,
-        z: i32,
-        chunks: &Query<&Chunk, T>,
         chunk: &Chunk,
-    ) -> Block {
-        let is_y0 = y >= 0;
-        let is_x0 = x >= 0;
-        let is_z0 = z >= 0;
-        let is_yh = y < CHUNK_H as i32;
-        let is_xw = x < CHUNK_W as i32;
-        let is_zd = z < CHUNK_D as i32;
-        if is_y0 && is_x0 && is_z0 && is_yh && is_xw && is_zd {
-            return chunk.get(x as usize, y as usize, z as usize).unwrap();
-        } else if !is_x0 {
-            if let Some(e) = self.left_chunk {
-                let chunk = match chunks.get(e) {
+        chunks: &Query<&Chunk, T>,
+        diagonals: [Option<Entity>; 4],
+        generator: Option<&WorldGen>,
+    ) -> ChunkSnapshot {
+        let border =
+            |e: Option<Entity>, get: &dyn Fn(&Chunk, usize, usize) -> Block, len: usize| {
+                let chunk = match chunks.get(e?) {
                     Ok(c) => c,
                     Err(err) => {
                         error!("error with query chunk: {err}");
-                        return Block::Air;
+                        return None;
                     }
                 };
-                return chunk.get(CHUNK_W - 1, y as usize, z as usize).unwrap();
-            } else {
-                return Block::Air;
-            }
-        } else if !is_z0 {
-            if let Some(e) = self.backward_chunk {
-                let chunk = match chunks.get(e) {
-                    Ok(c) => c,
-                    Err(err) => {
-                        error!("error with query chunk: {err}");
-                        return Block::Air;
+                let mut blocks = Vec::with_capacity(CHUNK_H * len);
+                for y in 0..CHUNK_H {
+                    for i in 0..len {
+                        blocks.push(get(chunk, y, i));
                     }
-                };
-                return chunk.get(x as usize, y as usize, CHUNK_D - 1).unwrap();
-            } else {
-                return Block::Air;
-            }
-        } else if !is_zd {
-            if let Some(e) = self.forward_chunk {
-                let chunk = match chunks.get(e) {
-                    Ok(c) => c,
-                    Err(err) => {
-                        error!("error with query chunk: {err}");
-                        return Block::Air;
+                }
+                Some(blocks)
+            };
+        ChunkSnapshot {
+            chunk: chunk.clone(),
+            climate: self.climate.clone(),
+            generator: generator.cloned(),
+            left: border(
+                self.left_chunk,
+                &|c, y, z| c.get(CHUNK_W - 1, y, z).unwrap(),
+                CHUNK_D,
+            ),
+            right: border(
+                self.right_chunk,
+                &|c, y, z| c.get(0, y, z).unwrap(),
+                CHUNK_D,
+            ),
+            backward: border(
+                self.backward_chunk,
+                &|c, y, x| c.get(x, y, CHUNK_D - 1).unwrap(),
+                CHUNK_W,
+            ),
+            forward: border(
+                self.forward_chunk,
+                &|c, y, x| c.get(x, y, 0).unwrap(),
+                CHUNK_W,
+            ),
+            corners: [
+                (0, CHUNK_W - 1, CHUNK_D - 1),
+                (1, 0, CHUNK_D - 1),
+                (2, CHUNK_W - 1, 0),
+                (3, 0, 0),
+            ]
+            .map(|(i, x, z)| border(diagonals[i], &|c, y, _| c.get(x, y, z).unwrap(), 1)),
+        }
+    }
+}
+
+/// Chunk blocks with the touching borders of its neighbours, indexed by `y * len + i`,
+/// and the touching columns of diagonal chunks for ambient occlusion
+pub struct ChunkSnapshot {
+    pub chunk: Chunk,
+    /// Shared with the [`RenderOfChunk`], see [`Self::climate`]
+    climate: Arc<OnceLock<ClimateMap>>,
+    generator: Option<WorldGen>,
+    left: Option<Vec<Block>>,
+    right: Option<Vec<Block>>,
+    backward: Option<Vec<Block>>,
+    forward: Option<Vec<Block>>,
+    /// See [`RenderOfChunk::diagonals`]
+    corners: [Option<Vec<Block>>; 4],
+}
+impl ChunkSnapshot {
+    /// Colors biome tinted faces. Sampled by the first mesh task that needs it, off the main thread,
+    /// and kept for later remeshes of the chunk
+    pub fn climate(&self) -> &ClimateMap {
+        self.climate
+            .get_or_init(|| ClimateMap::new(self.chunk.pos, self.generator.as_ref()))
+    }
+    /// Block at local position, one block past the horizontal borders is read from neighbours
+    pub fn get(&self, x: i32, y: i32, z: i32) -> Block {
+        if y < 0 || y >= CHUNK_H as i32 {
+            return Block::Air;
+        }
+        let outside_x = x < 0 || x >= CHUNK_W as i32;
+        let outside_z = z < 0 || z >= CHUNK_D as i32;
+        let (border, i, len) = if outside_x && outside_z {
+            let corner = usize::from(x >= 0) + 2 * usize::from(z >= 0);
+            (&self.corners[corner], 0, 1)
+        } else if x < 0 {
+            (&self.left, z, CHUNK_D)
+        } else if x >= CHUNK_W as i32 {
+            (&self.right, z, CHUNK_D)
+        } else if z < 0 {
+            (&self.backward, x, CHUNK_W)
+        } else if z >= CHUNK_D as i32 {
+            (&self.forward, x, CHUNK_W)
+        } else {
+            return self.chunk.get(x as usize, y as usize, z as usize).unwrap();
+        };
+        match border {
+            Some(blocks) => blocks[y as usize * len + i as usize],
+            None => Block::Air,
+        }
+    }
+}
+
+#[cfg(test)]
+impl ChunkSnapshot {
+    /// Chunk without neighbours, for meshing tests
+    pub fn isolated(chunk: Chunk) -> Self {
+        Self {
+            chunk,
+            climate: default(),
+            generator: None,
+            left: None,
+            right: None,
+            backward: None,
+            forward: None,
+            corners: [None, None, None, None],
+        }
+    }
+    /// Isolated chunk at the origin, `block` gives the id at each position or `None` for air
+    pub fn filled(
+        block: impl Fn(usize, usize, usize) -> Option<crate::voxel::blocks::BlockId>,
+    ) -> Self {
+        let mut chunk = Chunk::new_air(IVec2::ZERO);
+        for x in 0..CHUNK_W {
+            for y in 0..CHUNK_H {
+                for z in 0..CHUNK_D {
+                    if let Some(id) = block(x, y, z) {
+                        chunk.set(x, y, z, Block::solid(id));
                     }
-                };
-                return chunk.get(x as usize, y as usize, 0).unwrap();
-            } else {
-                return Block::Air;
+                }
             }
-        } else if !is_xw {
-            if let Some(e) = self.right_chunk {
-                let chunk = match chunks.get(e) {
-                    Ok(c) => c,
-                    Err(err) => {
-                        error!("error with query chunk: {err}");
-                        return Block::Air;
-                    }
-                };
-                return chunk.get(0, y as usize, z as usize).unwrap();
-            } else {
-                return Block::Air;
+        }
+        Self::isolated(chunk)
+    }
+}
+
+/// Offsets of left, right, backward and forward neighbours
+const NEIGHBOURS: [IVec2; 4] = [
+    IVec2::new(-1, 0),
+    IVec2::new(1, 0),
+    IVec2::new(0, -1),
+    IVec2::new(0, 1),
+];
+
+/// Offsets of chunks sharing only a corner, in [`RenderOfChunk::diagonals`] order
+const DIAGONALS: [IVec2; 4] = [
+    IVec2::new(-1, -1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, 1),
+    IVec2::new(1, 1),
+];
+
+fn neighbour_link(render: &mut RenderOfChunk, i: usize) -> &mut Option<Entity> {
+    match i {
+        0 => &mut render.left_chunk,
+        1 => &mut render.right_chunk,
+        2 => &mut render.backward_chunk,
+        _ => &mut render.forward_chunk,
+    }
+}
+
+/// Wires up neighbour links of spawned chunks and clears links to despawned ones
+fn link_neighbours(
+    map: Res<ChunkMap>,
+    added: Query<(Entity, &Chunk), Added<RenderOfChunk>>,
+    mut removed: RemovedComponents<Chunk>,
+    mut renders: Query<&mut RenderOfChunk>,
+) {
+    for removed in removed.read() {
+        for mut render in renders.iter_mut() {
+            for i in 0..NEIGHBOURS.len() {
+                let link = neighbour_link(&mut render, i);
+                if *link == Some(removed) {
+                    *link = None;
+                    render.mark_all_dirty();
+                }
+            }
+        }
+    }
+    for (entity, chunk) in added.iter() {
+        for (i, offset) in NEIGHBOURS.iter().enumerate() {
+            let neighbour = map.get(chunk.pos + *offset);
+            if let Ok(mut render) = renders.get_mut(entity) {
+                *neighbour_link(&mut render, i) = neighbour;
+                render.mark_all_dirty();
+            }
+            let Some(neighbour) = neighbour else {
+                continue;
+            };
+            if let Ok(mut render) = renders.get_mut(neighbour) {
+                // Opposite direction is the pair index (left <-> right, backward <-> forward)
+                *neighbour_link(&mut render, i ^ 1) = Some(entity);
+                render.mark_all_dirty();
+            }
+        }
+        // Ambient occlusion of diagonal chunks reads the shared corner column
+        for offset in DIAGONALS {
+            if let Some(mut render) = map
+                .get(chunk.pos + offset)
+                .and_then(|e| renders.get_mut(e).ok())
+            {
+                render.mark_all_dirty();
             }
-        } else if !is_y0 || !is_yh {
-            return Block::Air;
         }
-        Block::Solid(BlockId(0))
     }
 }
 
+/// Graphics settings change how every section is meshed
+fn remesh_all(mut renders: Query<&mut RenderOfChunk>) {
+    for mut render in renders.iter_mut() {
+        render.mark_all_dirty();
+    }
+}
+
+/// Child of chunk entity holding the opaque mesh of one section
+#[derive(Component)]
+pub struct SectionOfChunk {
+    pub index: usize,
+    /// Child entity with the alpha tested mesh, `None` while the section has no cutout faces
+    pub cutout: Option<Entity>,
+    /// Child entity with the blended mesh, `None` while the section has no translucent faces
+    pub translucent: Option<Entity>,
+}
+
+/// Meshes of one section. Opaque faces keep early depth testing, only cutout ones discard texels
+pub struct SectionMeshes {
+    pub opaque: Mesh,
+    pub cutout: Option<Mesh>,
+    pub translucent: Option<Mesh>,
+}
+
+/// Buffers of the section meshes, faces go to the one of their block [`Transparency`]
+pub struct SectionBuffers<'a> {
+    opaque: MeshBuffers<'a>,
+    cutout: MeshBuffers<'a>,
+    translucent: MeshBuffers<'a>,
+}
+
+impl<'a> SectionBuffers<'a> {
+    /// `opaque_faces` is reserved in the opaque buffers
+    pub fn new(opaque_faces: usize, mode: TextureMode, climate: &'a ClimateMap) -> Self {
+        Self {
+            opaque: MeshBuffers::with_faces(opaque_faces, mode, climate),
+            cutout: MeshBuffers::new(mode, climate),
+            translucent: MeshBuffers::new(mode, climate),
+        }
+    }
+    pub fn get(&mut self, transparency: Transparency) -> &mut MeshBuffers<'a> {
+        match transparency {
+            Transparency::Opaque => &mut self.opaque,
+            Transparency::Cutout => &mut self.cutout,
+            Transparency::Translucent => &mut self.translucent,
+        }
+    }
+    pub fn into_meshes(self) -> SectionMeshes {
+        let mesh = |buffers: MeshBuffers| (!buffers.is_empty()).then(|| buffers.into_mesh());
+        SectionMeshes {
+            opaque: self.opaque.into_mesh(),
+            cutout: mesh(self.cutout),
+            translucent: mesh(self.translucent),
+        }
+    }
+}
+
+/// Meshes being built in background
+#[derive(Component)]
+pub struct ChunkMeshTask(Task<SectionMeshes>);
+
+/// Materials shared by all sections
+struct VoxelMaterials {
+    opaque: Handle<VoxelMaterial>,
+    cutout: Handle<VoxelMaterial>,
+    translucent: Handle<VoxelMaterial>,
+}
+
+/// Removes mesh assets of the section entity and its children, entities are left to the caller
+pub fn free_section_meshes(
+    section_en: Entity,
+    sections: &Query<&SectionOfChunk>,
+    meshes: &Query<&Handle<Mesh>>,
+    meshes_assets: &mut Assets<Mesh>,
+) {
+    let children = sections
+        .get(section_en)
+        .map(|s| [s.cutout, s.translucent])
+        .unwrap_or_default();
+    for entity in std::iter::once(section_en).chain(children.into_iter().flatten()) {
+        if let Ok(mesh) = meshes.get(entity) {
+            meshes_assets.remove(mesh);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn make_meshes(
     mut commands: Commands,
     chunks: Query<&Chunk>,
     mut en_and_render: Query<(&mut RenderOfChunk, Entity), With<Chunk>>,
+    mut meshes_assets: ResMut<Assets<Mesh>>,
+    meshes: Query<&Handle<Mesh>>,
+    sections: Query<&SectionOfChunk>,
+    storage: Res<BlockStorage>,
+    settings: Res<GameSettings>,
+    generator: Option<Res<WorldGen>>,
+) {
+    let pool = AsyncComputeTaskPool::get();
+    let mesher: fn(&ChunkSnapshot, usize, &BlockTypes, bool) -> SectionMeshes =
+        match settings.graphics.meshing {
+            MeshingMode::Naive => create_chunk_mesh,
+            MeshingMode::Greedy => create_greedy_chunk_mesh,
+        };
+    let ambient_occlusion = settings.graphics.ambient_occlusion;
+    let dirty: Vec<Entity> = en_and_render
+        .iter()
+        .filter(|(render, _)| render.dirty_sections != 0)
+        .map(|(_, e)| e)
+        .collect();
+    for chunk_en in dirty {
+        let diagonals = en_and_render
+            .get(chunk_en)
+            .unwrap()
+            .0
+            .diagonals(|e| en_and_render.get(e).ok().map(|(render, _)| render));
+        let mut render = en_and_render.get_mut(chunk_en).unwrap().0;
+        let chunk = chunks.get(chunk_en).unwrap();
+        let snapshot = Arc::new(render.snapshot(chunk, &chunks, diagonals, generator.as_deref()));
+        for section in 0..SECTIONS {
+            if !render.is_dirty(section) {
+                continue;
+            }
+            if chunk.is_section_empty(section) {
+                // Empty sections cost nothing: no entity, no mesh
+                if let Some(section_en) = render.sections[section].take() {
+                    free_section_meshes(section_en, &sections, &meshes, &mut meshes_assets);
+                    commands.entity(section_en).despawn_recursive();
+                }
+                continue;
+            }
+            let section_en = match render.sections[section] {
+                Some(e) => e,
+                None => {
+                    let e = commands
+                        .spawn((
+                            SectionOfChunk {
+                                index: section,
+                                cutout: None,
+                                translucent: None,
+                            },
+                            SpatialBundle::from_transform(Transform::from_translation(
+                                section_translation(section),
+                            )),
+                        ))
+                        .set_parent(chunk_en)
+                        .id();
+                    render.sections[section] = Some(e);
+                    e
+                }
+            };
+            let snapshot = snapshot.clone();
+            let types = storage.types();
+            // Replacing a running task drops (cancels) it, so an outdated mesh is never applied
+            commands.entity(section_en).try_insert(ChunkMeshTask(
+                pool.spawn(async move { mesher(&snapshot, section, &types, ambient_occlusion) }),
+            ));
+        }
+        render.dirty_sections = 0;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_meshes(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut ChunkMeshTask, &mut SectionOfChunk, &Parent)>,
+    renders: Query<&RenderOfChunk>,
     meshes: Query<&Handle<Mesh>>,
     mut materials: ResMut<Assets<VoxelMaterial>>,
     mut meshes_assets: ResMut<Assets<Mesh>>,
-    mut transforms: Query<&mut Transform>,
+    mut shared: Local<Option<VoxelMaterials>>,
     storage: Res<BlockStorage>,
 ) {
-    for (mut render, chunk_en) in en_and_render.iter_mut() {
-        if render.is_generated_mesh {
+    let shared = shared.get_or_insert_with(|| VoxelMaterials {
+        opaque: materials.add(VoxelMaterial::new(&storage.imgs, AlphaMode::Opaque)),
+        cutout: materials.add(VoxelMaterial::new(&storage.imgs, AlphaMode::Mask(0.5))),
+        translucent: materials.add(VoxelMaterial::new(&storage.imgs, AlphaMode::Blend)),
+    });
+    if storage.is_changed() {
+        // Textures are rebuilt on block types reload
+        for handle in [&shared.opaque, &shared.cutout, &shared.translucent] {
+            if materials
+                .get(handle)
+                .is_some_and(|m| !m.uses(&storage.imgs))
+            {
+                let material = materials.get_mut(handle).unwrap();
+                *material = VoxelMaterial::new(&storage.imgs, material.alpha_mode());
+            }
+        }
+    }
+    for (section_en, mut task, mut section, parent) in tasks.iter_mut() {
+        let Some(result) = block_on(future::poll_once(&mut task.0)) else {
             continue;
-        } else {
-            render.is_generated_mesh = true;
+        };
+        commands.entity(section_en).remove::<ChunkMeshTask>();
+        if let Ok(render) = renders.get(parent.get()) {
+            if render.is_dirty(section.index) {
+                // Edited again while meshing, a fresh task will be spawned
+                continue;
+            }
         }
-        let chunk = chunks.get(chunk_en).unwrap();
-        let (mesh, translation) = create_chunk_mesh(&render, chunk, &storage, &chunks);
-        match meshes.get(chunk_en) {
-            Ok(mesh2) => match transforms.get_mut(chunk_en) {
-                Ok(mut transf) => {
-                    transf.translation = translation;
-                    let mesh2 = meshes_assets.get_mut(mesh2.clone()).unwrap();
-                    *mesh2 = mesh;
-                }
-                Err(_) => {
-                    let mesh2 = meshes_assets.get_mut(mesh2.clone()).unwrap();
-                    *mesh2 = mesh;
-                    commands
-                        .entity(chunk_en)
-                        .insert(TransformBundle::from_transform(
-                            Transform::from_translation(translation),
-                        ));
-                }
-            },
-            Err(_) => {
-                commands
-                    .entity(chunk_en)
-                    .insert(MaterialMeshBundle::<VoxelMaterial> {
-                        mesh: meshes_assets.add(mesh),
-                        material: materials.add(VoxelMaterial {
-                            color_texture: storage.imgs.texture.clone(),
-                        }),
-                        transform: Transform::from_translation(translation),
-                        ..Default::default()
-                    });
+        set_mesh(
+            &mut commands,
+            section_en,
+            result.opaque,
+            &shared.opaque,
+            &meshes,
+            &mut meshes_assets,
+        );
+        let section = &mut *section;
+        for (mesh, child, material) in [
+            (result.cutout, &mut section.cutout, &shared.cutout),
+            (
+                result.translucent,
+                &mut section.translucent,
+                &shared.translucent,
+            ),
+        ] {
+            set_child_mesh(
+                &mut commands,
+                section_en,
+                child,
+                mesh,
+                material,
+                &meshes,
+                &mut meshes_assets,
+            );
+        }
+    }
+}
+
+/// Updates, spawns or despawns the child of the section holding a mesh other than the opaque one
+fn set_child_mesh(
+    commands: &mut Commands,
+    section_en: Entity,
+    child: &mut Option<Entity>,
+    mesh: Option<Mesh>,
+    material: &Handle<VoxelMaterial>,
+    meshes: &Query<&Handle<Mesh>>,
+    meshes_assets: &mut Assets<Mesh>,
+) {
+    match (mesh, *child) {
+        (Some(mesh), Some(entity)) => {
+            set_mesh(commands, entity, mesh, material, meshes, meshes_assets)
+        }
+        (Some(mesh), None) => {
+            // The chunk may have been unloaded while its mesh was built
+            if commands.get_entity(section_en).is_none() {
+                return;
             }
+            let entity = commands
+                .spawn(MaterialMeshBundle {
+                    mesh: meshes_assets.add(mesh),
+                    material: material.clone(),
+                    ..default()
+                })
+                .set_parent(section_en)
+                .id();
+            *child = Some(entity);
+        }
+        (None, Some(entity)) => {
+            if let Ok(mesh) = meshes.get(entity) {
+                meshes_assets.remove(mesh);
+            }
+            commands.entity(entity).despawn_recursive();
+            *child = None;
+        }
+        (None, None) => {}
+    }
+}
+
+/// Replaces the mesh of the entity in place or gives it its first mesh
+fn set_mesh(
+    commands: &mut Commands,
+    entity: Entity,
+    mesh: Mesh,
+    material: &Handle<VoxelMaterial>,
+    meshes: &Query<&Handle<Mesh>>,
+    meshes_assets: &mut Assets<Mesh>,
+) {
+    match meshes.get(entity) {
+        Ok(handle) => {
+            *meshes_assets.get_mut(handle).unwrap() = mesh;
+        }
+        Err(_) => {
+            commands
+                .entity(entity)
+                .try_insert((meshes_assets.add(mesh), material.clone()));
         }
     }
 }
 // Thanks Tantan for this fast algorithm
-pub fn create_chunk_mesh<T: QueryFilter>(
-    chunk: &RenderOfChunk,
-    orig_chunk: &Chunk,
-    storage: &Res<BlockStorage>,
-    chunks: &Query<&Chunk, T>,
-) -> (Mesh, Vec3) {
-    // CHUNK_W is 16 => u32
-    let mut left_mask = [[0_u32; CHUNK_H]; CHUNK_D];
-    let mut right_mask = [[0_u32; CHUNK_H]; CHUNK_D];
+/// Mesh of one section, positions are relative to the section
+pub fn create_chunk_mesh(
+    snapshot: &ChunkSnapshot,
+    section: usize,
+    storage: &BlockTypes,
+    ambient_occlusion: bool,
+) -> SectionMeshes {
+    let orig_chunk = &snapshot.chunk;
+    let base_y = (section * SECTION_H) as i32;
+    // Bits are opaque cubes with a visible face, CHUNK_W is 16 => u32
+    let mut left_mask = [[0_u32; SECTION_H]; CHUNK_D];
+    let mut right_mask = [[0_u32; SECTION_H]; CHUNK_D];
 
     // CHUNK_D is 16 => u32
-    let mut forward_mask = [[0_u32; CHUNK_H]; CHUNK_W];
-    let mut backward_mask = [[0_u32; CHUNK_H]; CHUNK_W];
+    let mut forward_mask = [[0_u32; SECTION_H]; CHUNK_W];
+    let mut backward_mask = [[0_u32; SECTION_H]; CHUNK_W];
 
-    // CHUNK_H is 16 => u256
-    let mut up_mask = [[U256::from(0); CHUNK_W]; CHUNK_D];
-    let mut down_mask = [[U256::from(0); CHUNK_W]; CHUNK_D];
+    // SECTION_H is 16 => u32
+    let mut up_mask = [[0_u32; CHUNK_W]; CHUNK_D];
+    let mut down_mask = [[0_u32; CHUNK_W]; CHUNK_D];
 
     for z in 0..CHUNK_D {
-        for y in 0..CHUNK_H {
-            for x in -1..(CHUNK_W as i32 + 1) {
-                let block = chunk.get(x, y as i32, z as i32, chunks, orig_chunk);
-                let x = (x + 1) as usize;
-
-                // x (-)
-                set_bit_u32(&mut left_mask[z][y], x as u32, block.is_solid());
-                // x (+)
-                set_bit_u32(&mut right_mask[z][y], x as u32, block.is_solid());
-            }
+        for y in 0..SECTION_H {
+            (left_mask[z][y], right_mask[z][y]) = row_faces(
+                storage,
+                (-1..=CHUNK_W as i32).map(|x| snapshot.get(x, base_y + y as i32, z as i32)),
+                Side::Left,
+                Side::Right,
+            );
         }
     }
     for x in 0..CHUNK_W {
-        for y in 0..CHUNK_H {
-            for z in -1..(CHUNK_D as i32 + 1) {
-                let block = chunk.get(x as i32, y as i32, z, chunks, orig_chunk);
-                let z = (z + 1) as usize;
-                // z (-)
-                set_bit_u32(&mut forward_mask[x][y], z as u32, block.is_solid());
-                // z (+)
-                set_bit_u32(&mut backward_mask[x][y], z as u32, block.is_solid());
-            }
+        for y in 0..SECTION_H {
+            (forward_mask[x][y], backward_mask[x][y]) = row_faces(
+                storage,
+                (-1..=CHUNK_D as i32).map(|z| snapshot.get(x as i32, base_y + y as i32, z)),
+                Side::Forward,
+                Side::Back,
+            );
         }
     }
-
     for z in 0..CHUNK_D {
         for x in 0..CHUNK_W {
-            for y in -1..(CHUNK_H as i32 + 1) {
-                let block = chunk.get(x as i32, y, z as i32, chunks, orig_chunk);
-                let y = (y + 1) as usize;
-                // y (+)
-                set_bit_u256(&mut up_mask[z][x], y as u32, block.is_solid());
-                // y (-)
-                set_bit_u256(&mut down_mask[z][x], y as u32, block.is_solid());
-            }
+            (down_mask[z][x], up_mask[z][x]) = row_faces(
+                storage,
+                (-1..=SECTION_H as i32).map(|y| snapshot.get(x as i32, base_y + y, z as i32)),
+                Side::Bottom,
+                Side::Top,
+            );
         }
     }
 
+    // Bits 1..=len are the section itself, 0 and len + 1 are neighbours
+    let inner_x = ((1_u32 << CHUNK_W) - 1) << 1;
+    let inner_z = ((1_u32 << CHUNK_D) - 1) << 1;
+    let inner_y = ((1_u32 << SECTION_H) - 1) << 1;
+    let mut faces = 0;
     for z in 0..CHUNK_D {
-        for y in 0..CHUNK_H {
-            left_mask[z][y] = !(left_mask[z][y] << 1) & left_mask[z][y];
-            right_mask[z][y] = !(right_mask[z][y] >> 1) & right_mask[z][y];
+        for y in 0..SECTION_H {
+            faces += (left_mask[z][y] & inner_x).count_ones();
+            faces += (right_mask[z][y] & inner_x).count_ones();
         }
     }
     for x in 0..CHUNK_W {
-        for y in 0..CHUNK_H {
-            forward_mask[x][y] = !(forward_mask[x][y] << 1) & forward_mask[x][y];
-            backward_mask[x][y] = !(backward_mask[x][y] >> 1) & backward_mask[x][y];
+        for y in 0..SECTION_H {
+            faces += (forward_mask[x][y] & inner_z).count_ones();
+            faces += (backward_mask[x][y] & inner_z).count_ones();
         }
     }
     for z in 0..CHUNK_D {
         for x in 0..CHUNK_W {
-            down_mask[z][x] = !(down_mask[z][x] << 1) & down_mask[z][x];
-            up_mask[z][x] = !(up_mask[z][x] >> 1) & up_mask[z][x];
+            faces += (up_mask[z][x] & inner_y).count_ones();
+            faces += (down_mask[z][x] & inner_y).count_ones();
         }
     }
-    let mut mesh = void_mesh();
+    let mut buffers = SectionBuffers::new(faces as usize, storage.texture_mode, snapshot.climate());
 
     for x in 0..CHUNK_W {
-        for y in 0..CHUNK_H {
+        for y in 0..SECTION_H {
             for z in 0..CHUNK_D {
-                let mut mesh2 = void_mesh();
-
-                let Block::Solid(block) = orig_chunk.get(x, y, z).unwrap() else {
+                let block = orig_chunk.get(x, base_y as usize + y, z).unwrap();
+                let Block::Solid(id, state) = block else {
                     continue;
                 };
-                let sides = storage.get_or_default(block).sides.clone();
-                if y < CHUNK_H && z < CHUNK_D {
-                    if get_bit_u32(left_mask[z][y], x as u32 + 1) {
-                        mesh2.merge(sides.left.0.clone());
-                    }
-
-                    if get_bit_u32(right_mask[z][y], x as u32 + 1) {
-                        mesh2.merge(sides.right.0.clone());
-                    }
+                let offset = Vec3::new(
+                    VOXEL_SIZE * x as f32,
+                    VOXEL_SIZE * y as f32,
+                    VOXEL_SIZE * z as f32,
+                );
+                let pos = IVec3::new(x as i32, base_y + y as i32, z as i32);
+                if !storage.is_opaque_cube(block) || storage.rotation(block).is_some() {
+                    // Masks only know opaque cubes, the rest check their neighbours one by one
+                    let transparency = storage.properties(id).transparency;
+                    push_block_faces(
+                        snapshot,
+                        pos,
+                        offset,
+                        storage,
+                        buffers.get(transparency),
+                        ambient_occlusion,
+                    );
+                    continue;
                 }
-                if x < CHUNK_W && y < CHUNK_H {
-                    if get_bit_u32(forward_mask[x][y], z as u32 + 1) {
-                        mesh2.merge(sides.forward.0.clone());
+                let sides = &storage.get_or_default(id).sides;
+                let opaque = buffers.get(Transparency::Opaque);
+                let faces = [
+                    (Side::Left, &sides.left, left_mask[z][y], x),
+                    (Side::Right, &sides.right, right_mask[z][y], x),
+                    (Side::Forward, &sides.forward, forward_mask[x][y], z),
+                    (Side::Back, &sides.back, backward_mask[x][y], z),
+                    (Side::Bottom, &sides.bottom, down_mask[z][x], y),
+                    (Side::Top, &sides.top, up_mask[z][x], y),
+                ];
+                for (side, face, mask, i) in faces {
+                    if !get_bit_u32(mask, i as u32 + 1) {
+                        continue;
                     }
-                    if get_bit_u32(backward_mask[x][y], z as u32 + 1) {
-                        mesh2.merge(sides.back.0.clone());
+                    if ambient_occlusion {
+                        let occlusion = face_occlusion(snapshot, storage, pos, side, &face.0);
+                        opaque.push_shaded_face(&face.0, offset, state, occlusion);
+                    } else {
+                        opaque.push_face(&face.0, offset, state);
                     }
                 }
-                if x < CHUNK_W && z < CHUNK_D {
-                    if get_bit_u256(down_mask[z][x], y as u32 + 1) {
-                        mesh2.merge(sides.bottom.0.clone());
-                    }
-                    if get_bit_u256(up_mask[z][x], y as u32 + 1) {
-                        mesh2.merge(sides.top.0.clone());
-                    }
+            }
+        }
+    }
+    buffers.into_meshes()
+}
+
+/// Faces of an opaque cube row looking to `neg` and `pos` sides.
+/// Bit `i + 1` is the block `i` of the row, bits 0 and `len + 1` are the blocks around it
+fn row_faces(
+    storage: &BlockTypes,
+    row: impl Iterator<Item = Block>,
+    neg: Side,
+    pos: Side,
+) -> (u32, u32) {
+    let (mut cubes, mut hides_neg, mut hides_pos) = (0, 0, 0);
+    for (i, block) in row.enumerate() {
+        set_bit_u32(&mut cubes, i as u32, storage.is_opaque_cube(block));
+        set_bit_u32(&mut hides_neg, i as u32, storage.occludes(block, neg));
+        set_bit_u32(&mut hides_pos, i as u32, storage.occludes(block, pos));
+    }
+    (cubes & !(hides_pos << 1), cubes & !(hides_neg >> 1))
+}
+
+/// Ambient occlusion of the corners of the cube face on `side` of the block at `pos`, in the order
+/// of `face` corners: from 0 to 3 opaque cubes among the three blocks in front of the corner
+/// (both sides touching is fully dark), then flipped so 3 is an open corner
+pub fn face_occlusion(
+    snapshot: &ChunkSnapshot,
+    storage: &BlockTypes,
+    pos: IVec3,
+    side: Side,
+    face: &FaceTemplate,
+) -> [u8; 4] {
+    let (axis, _) = side.axis();
+    let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+    let front = pos + side.normal();
+    let occludes = |p: IVec3| storage.is_opaque_cube(snapshot.get(p.x, p.y, p.z));
+    face.positions.map(|corner| {
+        // Corners are relative to the block center, their signs point to the touching blocks
+        let toward = |axis: usize| {
+            let mut dir = IVec3::ZERO;
+            dir[axis] = if corner[axis] > 0. { 1 } else { -1 };
+            dir
+        };
+        let (side_a, side_b) = (occludes(front + toward(a)), occludes(front + toward(b)));
+        if side_a && side_b {
+            return 0;
+        }
+        let diagonal = occludes(front + toward(a) + toward(b));
+        3 - side_a as u8 - side_b as u8 - diagonal as u8
+    })
+}
+
+/// Visible faces of the block at `pos` one by one, for blocks the bit masks can't handle.
+/// Cube faces get ambient occlusion when it's on, model faces are never shaded
+pub fn push_block_faces(
+    snapshot: &ChunkSnapshot,
+    pos: IVec3,
+    offset: Vec3,
+    storage: &BlockTypes,
+    buffers: &mut MeshBuffers,
+    ambient_occlusion: bool,
+) {
+    let block = snapshot.get(pos.x, pos.y, pos.z);
+    let Block::Solid(id, state) = block else {
+        return;
+    };
+    let visible = |side: Side| {
+        let next = pos + side.normal();
+        storage.face_visible(block, snapshot.get(next.x, next.y, next.z), side)
+    };
+    let block_type = storage.get_or_default(id);
+    let rotation = storage.rotation(block);
+    let turned = |face: &FaceTemplate| match rotation {
+        Some(m) => face.transformed(m),
+        None => *face,
+    };
+    match &block_type.model {
+        None => {
+            for side in Side::ALL {
+                if !visible(side) {
+                    continue;
+                }
+                // Model side that is turned to `side`
+                let model_side = rotation.map_or(side, |m| side.transformed(m.transpose()));
+                let face = turned(&block_type.sides.get(model_side).0);
+                let occlusion = match ambient_occlusion {
+                    true => face_occlusion(snapshot, storage, pos, side, &face),
+                    false => NO_OCCLUSION,
+                };
+                buffers.push_shaded_face(&face, offset, state, occlusion);
+            }
+        }
+        Some(model) => {
+            for face in model {
+                let cull = face
+                    .cull
+                    .map(|side| rotation.map_or(side, |m| side.transformed(m)));
+                if cull.is_none_or(visible) {
+                    buffers.push_face(&turned(&face.face), offset, state);
                 }
-                mesh2.translate_by(Vec3::new(
-                    VOXEL_SIZE * x as f32,
-                    VOXEL_SIZE * y as f32,
-                    VOXEL_SIZE * z as f32,
-                ));
-                mesh.merge(mesh2);
             }
         }
     }
-    let trans_ = Vec3::new(
-        orig_chunk.pos.x as f32 * VOXEL_SIZE + VOXEL_SIZE / 2.,
+}
+
+/// Transform translation of the chunk at chunk coordinate `pos`
+pub fn chunk_translation(pos: IVec2) -> Vec3 {
+    Vec3::new(
+        (pos.x * CHUNK_W as i32) as f32 * VOXEL_SIZE + VOXEL_SIZE / 2.,
         VOXEL_SIZE / 2.,
-        orig_chunk.pos.y as f32 * VOXEL_SIZE + VOXEL_SIZE / 2.,
-    );
-    (mesh, trans_)
+        (pos.y * CHUNK_D as i32) as f32 * VOXEL_SIZE + VOXEL_SIZE / 2.,
+    )
+}
+
+/// Translation of section relative to its chunk
+pub fn section_translation(section: usize) -> Vec3 {
+    Vec3::new(0., (section * SECTION_H) as f32 * VOXEL_SIZE, 0.)
 }
 
 fn set_bit_u32(num: &mut u32, n: u32, x: bool) {
@@ -289,9 +906,181 @@ fn set_bit_u32(num: &mut u32, n: u32, x: bool) {
 fn get_bit_u32(num: u32, n: u32) -> bool {
     ((num >> n) & 1) != 0
 }
-fn set_bit_u256(num: &mut U256, n: u32, x: bool) {
-    *num |= (U256::from(x as u8)) << n
-}
-fn get_bit_u256(num: U256, n: u32) -> bool {
-    ((num >> n) & U256::from(1)) != U256::from(0)
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        hint::black_box,
+        time::{Duration, Instant},
+    };
+
+    use bevy::{render::mesh::VertexAttributeValues, utils::HashMap};
+
+    use super::*;
+    use crate::{
+        interface::render::util::{ATTRIBUTE_LAYER, ATTRIBUTE_OCCLUSION},
+        voxel::blocks::{BlockId, BlockProperties, BlockState, StateProperty},
+    };
+
+    const ITERATIONS: u32 = 20;
+
+    /// Mean time to mesh every section of the chunk
+    fn time_chunk(mesher: impl Fn(usize) -> SectionMeshes) -> Duration {
+        // Warm up caches and allocator
+        (0..SECTIONS).for_each(|section| drop(black_box(mesher(section))));
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            for section in 0..SECTIONS {
+                black_box(mesher(section));
+            }
+        }
+        start.elapsed() / ITERATIONS
+    }
+
+    /// Meshing before face templates: a prebuilt `Mesh` per cube side is cloned and merged
+    /// for every visible face, translated and merged again per block
+    fn merge_mesher(
+        snapshot: &ChunkSnapshot,
+        section: usize,
+        types: &BlockTypes,
+        side_meshes: &HashMap<(BlockId, usize), Mesh>,
+    ) -> Mesh {
+        let empty = || MeshBuffers::new(types.texture_mode, snapshot.climate()).into_mesh();
+        let mut mesh = empty();
+        let base_y = (section * SECTION_H) as i32;
+        for x in 0..CHUNK_W as i32 {
+            for y in base_y..base_y + SECTION_H as i32 {
+                for z in 0..CHUNK_D as i32 {
+                    let block = snapshot.get(x, y, z);
+                    let Block::Solid(id, _) = block else {
+                        continue;
+                    };
+                    let mut block_mesh = empty();
+                    for side in Side::ALL {
+                        let next = IVec3::new(x, y, z) + side.normal();
+                        if types.face_visible(block, snapshot.get(next.x, next.y, next.z), side) {
+                            block_mesh.merge(side_meshes[&(id, side as usize)].clone());
+                        }
+                    }
+                    block_mesh.translate_by(Vec3::new(x as f32, (y - base_y) as f32, z as f32));
+                    mesh.merge(block_mesh);
+                }
+            }
+        }
+        mesh
+    }
+
+    /// Meshing time of a full 16x128x16 chunk against the old `Mesh::merge` path:
+    /// `cargo test --release mesh_benchmark -- --ignored --nocapture`
+    #[test]
+    #[ignore = "benchmark"]
+    fn mesh_benchmark() {
+        let ids = [BlockId(1), BlockId(2)];
+        let types = BlockTypes::test_cubes(&ids, TextureMode::Atlas);
+        let climate = ClimateMap::new(IVec2::ZERO, None);
+        let mut side_meshes = HashMap::new();
+        for id in ids {
+            for side in Side::ALL {
+                let mut buffers = MeshBuffers::new(types.texture_mode, &climate);
+                let face = &types.get_or_default(id).sides.get(side).0;
+                buffers.push_face(face, Vec3::ZERO, default());
+                side_meshes.insert((id, side as usize), buffers.into_mesh());
+            }
+        }
+        let chunks = [
+            (
+                "full",
+                ChunkSnapshot::filled(|_, y, _| Some(ids[y / 4 % 2])),
+            ),
+            (
+                "checkerboard",
+                ChunkSnapshot::filled(|x, y, z| ((x + y + z) % 2 == 0).then_some(ids[y / 4 % 2])),
+            ),
+        ];
+        for (chunk, snapshot) in &chunks {
+            let merged = time_chunk(|section| SectionMeshes {
+                opaque: merge_mesher(snapshot, section, &types, &side_meshes),
+                cutout: None,
+                translucent: None,
+            });
+            let templates =
+                time_chunk(|section| create_chunk_mesh(snapshot, section, &types, false));
+            let greedy =
+                time_chunk(|section| create_greedy_chunk_mesh(snapshot, section, &types, false));
+            println!("{chunk} chunk: merge {merged:?}, templates {templates:?}, greedy {greedy:?}");
+            assert!(
+                templates * 4 < merged,
+                "{chunk} chunk: templates {templates:?} are not much faster than merge {merged:?}"
+            );
+        }
+    }
+
+    /// Sorted occlusion of the vertices of `id` faces, test cube textures tell the block apart
+    fn occlusions(mesh: Option<&Mesh>, id: BlockId) -> Vec<f32> {
+        let mesh = mesh.expect("no mesh");
+        let (
+            Some(VertexAttributeValues::Float32(occlusion)),
+            Some(VertexAttributeValues::Uint32(layers)),
+        ) = (
+            mesh.attribute(ATTRIBUTE_OCCLUSION),
+            mesh.attribute(ATTRIBUTE_LAYER),
+        )
+        else {
+            panic!("no occlusion or layers");
+        };
+        let mut values: Vec<f32> = occlusion
+            .iter()
+            .zip(layers)
+            .filter(|(_, layer)| **layer / 6 == id.0)
+            .map(|(light, _)| *light)
+            .collect();
+        values.sort_by(f32::total_cmp);
+        values
+    }
+
+    #[test]
+    fn occlusion_of_turned_and_cutout_cubes() {
+        let [cube, plain, log, leaves] = [2, 3, 4, 5].map(BlockId);
+        let ids = [BlockId(1), cube, plain, log, leaves];
+        let types = BlockTypes::test_cubes(&ids, TextureMode::Array)
+            .with_properties(
+                log,
+                BlockProperties {
+                    states: vec![StateProperty::Axis].into(),
+                    ..default()
+                },
+            )
+            .with_properties(
+                leaves,
+                BlockProperties {
+                    transparency: Transparency::Cutout,
+                    ..default()
+                },
+            );
+        // The block lies in a corner of cubes, a turned cube is shaded like a plain one
+        let snapshot = |block| {
+            let mut chunk = Chunk::new_air(IVec2::ZERO);
+            chunk.set(4, 4, 4, block);
+            for (x, y, z) in [(3, 4, 4), (4, 4, 3), (3, 5, 4), (4, 5, 3), (3, 4, 3)] {
+                chunk.set(x, y, z, Block::solid(cube));
+            }
+            ChunkSnapshot::isolated(chunk)
+        };
+        let turned = snapshot(Block::Solid(log, BlockState::default().with_axis(0)));
+        for mesher in [create_chunk_mesh, create_greedy_chunk_mesh] {
+            let meshes = mesher(&snapshot(Block::solid(plain)), 0, &types, true);
+            let expected = occlusions(Some(&meshes.opaque), plain);
+            assert_eq!(expected.len(), 4 * 4);
+            assert!(expected.iter().any(|light| *light < 1.));
+            let meshes = mesher(&turned, 0, &types, true);
+            assert_eq!(occlusions(Some(&meshes.opaque), log), expected);
+            let meshes = mesher(&snapshot(Block::solid(leaves)), 0, &types, true);
+            let shaded = occlusions(meshes.cutout.as_ref(), leaves);
+            assert!(shaded.iter().any(|light| *light < 1.));
+
+            let meshes = mesher(&turned, 0, &types, false);
+            let unshaded = occlusions(Some(&meshes.opaque), log);
+            assert!(unshaded.iter().all(|light| *light == 1.));
+        }
+    }
 }
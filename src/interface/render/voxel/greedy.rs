@@ -0,0 +1,382 @@
+/// Greedy meshing: coplanar visible faces with the same texture are merged into one quad,
+/// the texture is repeated inside the quad by the shader (see `ATTRIBUTE_TILE`)
+use crate::{
+    interface::{
+        constants::VOXEL_SIZE,
+        render::util::{quad_indices, FaceTemplate, FaceTexture, NO_OCCLUSION},
+    },
+    prelude::*,
+    voxel::blocks::{Block, BlockState, Side, Transparency},
+};
+
+use super::{
+    blocks::storage::BlockTypes,
+    chunk::{face_occlusion, push_block_faces, ChunkSnapshot, SectionBuffers, SectionMeshes},
+};
+
+/// Texture of a visible face, its mesh, its corner occlusion and the block state tinting it
+type MaskFace = (FaceTexture, Transparency, [u8; 4], BlockState);
+
+/// Axis of the face normal, face plane axes are the other two
+#[derive(Clone, Copy)]
+enum FaceAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl FaceAxis {
+    fn size(self) -> usize {
+        match self {
+            Self::X => CHUNK_W,
+            Self::Y => SECTION_H,
+            Self::Z => CHUNK_D,
+        }
+    }
+    /// Plane axes `(a, b)`
+    fn plane(self) -> (FaceAxis, FaceAxis) {
+        match self {
+            Self::X => (Self::Y, Self::Z),
+            Self::Y => (Self::X, Self::Z),
+            Self::Z => (Self::X, Self::Y),
+        }
+    }
+    fn side(self, sign: i32) -> Side {
+        Side::from_axis(self as usize, sign > 0)
+    }
+}
+
+/// Quad over cells `a0..a1`, `b0..b1` of slice `s`, face looking to `sign` of `axis`
+fn greedy_face(
+    axis: FaceAxis,
+    sign: i32,
+    s: usize,
+    (a0, a1): (usize, usize),
+    (b0, b1): (usize, usize),
+    texture: FaceTexture,
+) -> FaceTemplate {
+    let (pa, pb) = axis.plane();
+    let plane = s as f32 + 0.5 * sign as f32;
+    let (a0, a1, b0, b1) = (
+        a0 as f32 - 0.5,
+        a1 as f32 - 0.5,
+        b0 as f32 - 0.5,
+        b1 as f32 - 0.5,
+    );
+    // Corners in `square_face` order, X and Z list (a0,b0),(a0,b1),(a1,b1),(a1,b0), Y swaps
+    let corners = match axis {
+        FaceAxis::Y => [(a0, b0), (a1, b0), (a1, b1), (a0, b1)],
+        _ => [(a0, b0), (a0, b1), (a1, b1), (a1, b0)],
+    };
+    let positions = corners.map(|(a, b)| {
+        let mut pos = Vec3::ZERO;
+        pos[axis as usize] = plane;
+        pos[pa as usize] = a;
+        pos[pb as usize] = b;
+        (pos * VOXEL_SIZE).to_array()
+    });
+    // Same orientation as `square_face`: u runs against the first texture axis, v against the second
+    let uvs = corners.map(|(a, b)| match axis {
+        FaceAxis::X => [b1 - b, a1 - a],
+        FaceAxis::Y | FaceAxis::Z => [a1 - a, b1 - b],
+    });
+    FaceTemplate {
+        positions,
+        uvs,
+        indices: quad_indices(sign < 0),
+        texture,
+    }
+}
+
+/// Mesh of one section, positions are relative to the section
+pub fn create_greedy_chunk_mesh(
+    snapshot: &ChunkSnapshot,
+    section: usize,
+    storage: &BlockTypes,
+    ambient_occlusion: bool,
+) -> SectionMeshes {
+    let base = IVec3::new(0, (section * SECTION_H) as i32, 0);
+    let mut buffers = SectionBuffers::new(0, storage.texture_mode, snapshot.climate());
+    for axis in [FaceAxis::X, FaceAxis::Y, FaceAxis::Z] {
+        let (pa, pb) = axis.plane();
+        let (len_a, len_b) = (pa.size(), pb.size());
+        // Only equal faces merge. Biome tints are interpolated between the corners of merged quads
+        let mut mask: Vec<Option<MaskFace>> = vec![None; len_a * len_b];
+        for sign in [-1, 1] {
+            for s in 0..axis.size() {
+                // Visible faces of this slice
+                for b in 0..len_b {
+                    for a in 0..len_a {
+                        let mut pos = base;
+                        pos[axis as usize] += s as i32;
+                        pos[pa as usize] += a as i32;
+                        pos[pb as usize] += b as i32;
+                        let block = snapshot.get(pos.x, pos.y, pos.z);
+                        let (block_type, state) = match block {
+                            Block::Solid(id, state) => (storage.get_or_default(id), state),
+                            Block::Air => {
+                                mask[b * len_a + a] = None;
+                                continue;
+                            }
+                        };
+                        let side = axis.side(sign);
+                        let next = pos + side.normal();
+                        let next = snapshot.get(next.x, next.y, next.z);
+                        // Models and turned blocks are not merged, they are added block by block below
+                        mask[b * len_a + a] = if block_type.model.is_none()
+                            && storage.rotation(block).is_none()
+                            && storage.face_visible(block, next, side)
+                        {
+                            let face = &block_type.sides.get(side).0;
+                            let occlusion = match ambient_occlusion {
+                                true => face_occlusion(snapshot, storage, pos, side, face),
+                                false => NO_OCCLUSION,
+                            };
+                            let transparency = block_type.properties.transparency;
+                            Some((face.texture, transparency, occlusion, state))
+                        } else {
+                            None
+                        };
+                    }
+                }
+                // Grow each quad along `a`, then along `b` while the whole row matches
+                for b in 0..len_b {
+                    let mut a = 0;
+                    while a < len_a {
+                        let Some(tile) = mask[b * len_a + a] else {
+                            a += 1;
+                            continue;
+                        };
+                        // Shading across a merged quad would be stretched, only evenly lit faces merge
+                        let [corner, ..] = tile.2;
+                        let mergeable = tile.2.iter().all(|level| *level == corner);
+                        let mut w = 1;
+                        while mergeable && a + w < len_a && mask[b * len_a + a + w] == Some(tile) {
+                            w += 1;
+                        }
+                        let mut h = 1;
+                        'grow: while mergeable && b + h < len_b {
+                            for i in a..a + w {
+                                if mask[(b + h) * len_a + i] != Some(tile) {
+                                    break 'grow;
+                                }
+                            }
+                            h += 1;
+                        }
+                        for j in b..b + h {
+                            for i in a..a + w {
+                                mask[j * len_a + i] = None;
+                            }
+                        }
+                        buffers.get(tile.1).push_shaded_face(
+                            &greedy_face(axis, sign, s, (a, a + w), (b, b + h), tile.0),
+                            Vec3::ZERO,
+                            tile.3,
+                            tile.2,
+                        );
+                        a += w;
+                    }
+                }
+            }
+        }
+    }
+    for x in 0..CHUNK_W {
+        for y in 0..SECTION_H {
+            for z in 0..CHUNK_D {
+                let pos = base + IVec3::new(x as i32, y as i32, z as i32);
+                let block = snapshot.get(pos.x, pos.y, pos.z);
+                let Block::Solid(id, _) = block else {
+                    continue;
+                };
+                if storage.get_or_default(id).model.is_none() && storage.rotation(block).is_none() {
+                    continue;
+                }
+                let offset = Vec3::new(x as f32, y as f32, z as f32) * VOXEL_SIZE;
+                let transparency = storage.properties(id).transparency;
+                push_block_faces(
+                    snapshot,
+                    pos,
+                    offset,
+                    storage,
+                    buffers.get(transparency),
+                    ambient_occlusion,
+                );
+            }
+        }
+    }
+    buffers.into_meshes()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{render::mesh::VertexAttributeValues, utils::HashMap};
+
+    use super::*;
+    use crate::{
+        config::TextureMode,
+        interface::{
+            constants::VOXEL_SIZE,
+            render::{
+                util::ATTRIBUTE_LAYER,
+                voxel::chunk::{create_chunk_mesh, section_translation},
+            },
+        },
+        voxel::blocks::{BlockId, BlockProperties, Transparency},
+    };
+
+    type Mesher = fn(&ChunkSnapshot, usize, &BlockTypes, bool) -> SectionMeshes;
+
+    /// Area covered by the mesh per face normal, face plane and texture layer
+    type Coverage = HashMap<(IVec3, i32, u32), f32>;
+
+    const IDS: [BlockId; 3] = [BlockId(1), BlockId(2), BlockId(3)];
+
+    /// Vertex count and coverage of all sections
+    fn mesh_all(
+        mesher: Mesher,
+        snapshot: &ChunkSnapshot,
+        ambient_occlusion: bool,
+    ) -> (usize, Coverage) {
+        let types = BlockTypes::test_cubes(&IDS, TextureMode::Array);
+        let (mut vertices, mut coverage) = (0, Coverage::new());
+        for section in 0..SECTIONS {
+            let meshes = mesher(snapshot, section, &types, ambient_occlusion);
+            assert!(meshes.cutout.is_none() && meshes.translucent.is_none());
+            vertices += meshes.opaque.count_vertices();
+            add_coverage(&meshes.opaque, section, &mut coverage);
+        }
+        (vertices, coverage)
+    }
+
+    fn add_coverage(mesh: &Mesh, section: usize, coverage: &mut Coverage) {
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("no positions");
+        };
+        let Some(VertexAttributeValues::Uint32(layers)) = mesh.attribute(ATTRIBUTE_LAYER) else {
+            panic!("no layers");
+        };
+        let Some(indices) = mesh.indices() else {
+            return;
+        };
+        let indices: Vec<usize> = indices.iter().collect();
+        let base = section_translation(section);
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from_array(positions[triangle[i]]) + base);
+            let cross = (b - a).cross(c - a);
+            let normal = cross.normalize().round().as_ivec3();
+            let plane = (normal.as_vec3().dot(a) * 2.).round() as i32;
+            *coverage
+                .entry((normal, plane, layers[triangle[0]]))
+                .or_default() += cross.length() / 2.;
+        }
+    }
+
+    fn assert_same_coverage(naive: &Coverage, greedy: &Coverage) {
+        assert_eq!(naive.len(), greedy.len());
+        for (key, area) in naive {
+            let other = greedy.get(key).copied().unwrap_or_default();
+            assert!((area - other).abs() < 1e-3, "{key:?}: {area} != {other}");
+        }
+    }
+
+    /// Greedy has at most the vertices of naive and draws the same faces
+    fn compare(snapshot: &ChunkSnapshot) -> (usize, usize) {
+        let mut counts = (0, 0);
+        for ambient_occlusion in [false, true] {
+            let (naive, naive_coverage) = mesh_all(create_chunk_mesh, snapshot, ambient_occlusion);
+            let (greedy, greedy_coverage) =
+                mesh_all(create_greedy_chunk_mesh, snapshot, ambient_occlusion);
+            assert!(greedy <= naive, "greedy {greedy} > naive {naive}");
+            assert_same_coverage(&naive_coverage, &greedy_coverage);
+            counts = (naive, greedy);
+        }
+        counts
+    }
+
+    #[test]
+    fn flat_slab() {
+        let (naive, greedy) = compare(&ChunkSnapshot::filled(|_, y, _| (y < 4).then_some(IDS[1])));
+        // Each of the 6 sides is a single quad
+        assert_eq!(greedy, 6 * 4);
+        assert!(naive > greedy);
+    }
+
+    #[test]
+    fn checkerboard() {
+        // Nothing to merge, both draw every face of every block
+        let (naive, greedy) = compare(&ChunkSnapshot::filled(|x, y, z| {
+            ((x + y + z) % 2 == 0 && y < 8).then_some(IDS[1])
+        }));
+        assert_eq!(naive, greedy);
+    }
+
+    /// Faces drawn into the opaque, cutout and translucent meshes, in full block faces
+    fn face_counts(mesher: Mesher, snapshot: &ChunkSnapshot, types: &BlockTypes) -> [usize; 3] {
+        let mut areas = [0.; 3];
+        for section in 0..SECTIONS {
+            let meshes = mesher(snapshot, section, types, true);
+            for (area, mesh) in areas.iter_mut().zip([
+                Some(&meshes.opaque),
+                meshes.cutout.as_ref(),
+                meshes.translucent.as_ref(),
+            ]) {
+                let mut coverage = Coverage::new();
+                if let Some(mesh) = mesh {
+                    add_coverage(mesh, section, &mut coverage);
+                }
+                *area += coverage.values().sum::<f32>();
+            }
+        }
+        areas.map(|area| (area / (VOXEL_SIZE * VOXEL_SIZE)).round() as usize)
+    }
+
+    #[test]
+    fn transparent_neighbours() {
+        let transparency = |transparency| BlockProperties {
+            transparency,
+            ..default()
+        };
+        let [opaque, cutout, glass, other_glass] = [2, 3, 4, 5].map(BlockId);
+        let types = BlockTypes::test_cubes(
+            &[BlockId(1), opaque, cutout, glass, other_glass],
+            TextureMode::Array,
+        )
+        .with_properties(cutout, transparency(Transparency::Cutout))
+        .with_properties(glass, transparency(Transparency::Translucent))
+        .with_properties(other_glass, transparency(Transparency::Translucent));
+        let cases = [
+            // Transparent blocks never hide the opaque face behind them
+            ((opaque, cutout), [6, 5, 0]),
+            ((opaque, glass), [6, 0, 5]),
+            ((cutout, cutout), [0, 12, 0]),
+            // No faces inside the same glass, but between different ones
+            ((glass, glass), [0, 0, 10]),
+            ((glass, other_glass), [0, 0, 12]),
+            ((cutout, glass), [0, 6, 6]),
+        ];
+        for ((a, b), expected) in cases {
+            let snapshot = ChunkSnapshot::filled(|x, y, z| match (x, y, z) {
+                (4, 4, 4) => Some(a),
+                (5, 4, 4) => Some(b),
+                _ => None,
+            });
+            for mesher in [create_chunk_mesh as Mesher, create_greedy_chunk_mesh] {
+                assert_eq!(
+                    face_counts(mesher, &snapshot, &types),
+                    expected,
+                    "{a:?} next to {b:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_textures() {
+        compare(&ChunkSnapshot::filled(|x, y, z| {
+            let hole = (x * 5 + z * 3) % 7 == 0 && y > 20;
+            (y < 40 && !hole).then_some(IDS[(x / 3 + y / 5 + z / 4) % 3])
+        }));
+    }
+}
@@ -1,5 +1,7 @@
 pub mod blocks;
 pub mod chunk;
+mod greedy;
+mod stream;
 use bevy::{
     pbr::{MaterialPipeline, MaterialPipelineKey},
     render::{
@@ -7,10 +9,16 @@ use bevy::{
         render_resource::{AsBindGroup, RenderPipelineDescriptor, SpecializedMeshPipelineError},
     },
 };
-use blocks::load::BlockLoadPlugin;
+use blocks::{image_storage::BlockImageStorage, load::BlockLoadPlugin};
 use chunk::ChunkRenderPlugin;
+use stream::ChunkStreamPlugin;
 
-use crate::prelude::*;
+use crate::{
+    interface::render::util::{
+        ATTRIBUTE_ANIMATION, ATTRIBUTE_LAYER, ATTRIBUTE_OCCLUSION, ATTRIBUTE_TILE,
+    },
+    prelude::*,
+};
 
 pub struct VoxelRenderPlugin;
 
@@ -18,6 +26,7 @@ impl Plugin for VoxelRenderPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((
             ChunkRenderPlugin,
+            ChunkStreamPlugin,
             BlockLoadPlugin,
             MaterialPlugin::<VoxelMaterial>::default(),
         ));
@@ -25,11 +34,48 @@ impl Plugin for VoxelRenderPlugin {
 }
 
 #[derive(Clone, AsBindGroup, Asset, TypePath)]
+#[bind_group_data(VoxelMaterialKey)]
 pub struct VoxelMaterial {
+    /// Block atlas, sampled with [`ATTRIBUTE_TILE`]
     #[texture(0)]
     #[sampler(1)]
-    color_texture: Handle<Image>,
+    pub color_texture: Handle<Image>,
+    /// Block texture array, sampled with [`ATTRIBUTE_LAYER`] instead of the atlas when set
+    #[texture(2, dimension = "2d_array")]
+    #[sampler(3)]
+    pub array_texture: Option<Handle<Image>>,
+    /// `Opaque`, `Mask` for cutout blocks or `Blend` for translucent ones
+    alpha_mode: AlphaMode,
+}
+impl VoxelMaterial {
+    pub fn new(imgs: &BlockImageStorage, alpha_mode: AlphaMode) -> Self {
+        Self {
+            color_texture: imgs.texture.clone(),
+            array_texture: imgs.array.clone(),
+            alpha_mode,
+        }
+    }
+    /// Samples the current textures of `imgs`
+    pub fn uses(&self, imgs: &BlockImageStorage) -> bool {
+        self.color_texture == imgs.texture && self.array_texture == imgs.array
+    }
 }
+
+/// Pipeline variant of [`VoxelMaterial`]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VoxelMaterialKey {
+    alpha_cutout: bool,
+    texture_array: bool,
+}
+impl From<&VoxelMaterial> for VoxelMaterialKey {
+    fn from(material: &VoxelMaterial) -> Self {
+        Self {
+            alpha_cutout: matches!(material.alpha_mode, AlphaMode::Mask(_)),
+            texture_array: material.array_texture.is_some(),
+        }
+    }
+}
+
 impl Material for VoxelMaterial {
     fn fragment_shader() -> bevy::render::render_resource::ShaderRef {
         bevy::render::render_resource::ShaderRef::Path("asset://shaders/voxel.wgsl".into())
@@ -37,19 +83,40 @@ impl Material for VoxelMaterial {
     fn vertex_shader() -> bevy::render::render_resource::ShaderRef {
         bevy::render::render_resource::ShaderRef::Path("asset://shaders/voxel.wgsl".into())
     }
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
 
     fn specialize(
         _pipeline: &MaterialPipeline<Self>,
         descriptor: &mut RenderPipelineDescriptor,
         layout: &MeshVertexBufferLayout,
-        _key: MaterialPipelineKey<Self>,
+        key: MaterialPipelineKey<Self>,
     ) -> Result<(), SpecializedMeshPipelineError> {
+        let texture = match key.bind_group_data.texture_array {
+            true => ATTRIBUTE_LAYER.at_shader_location(3),
+            false => ATTRIBUTE_TILE.at_shader_location(3),
+        };
         let vertex_layout = layout.get_layout(&[
             Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
-            //ATTRIBUTE_BLEND_COLOR.at_shader_location(1),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(1),
             Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            texture,
+            ATTRIBUTE_ANIMATION.at_shader_location(4),
+            ATTRIBUTE_OCCLUSION.at_shader_location(5),
         ])?;
         descriptor.vertex.buffers = vec![vertex_layout];
+        if key.bind_group_data.texture_array {
+            descriptor.vertex.shader_defs.push("TEXTURE_ARRAY".into());
+        }
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            if key.bind_group_data.alpha_cutout {
+                fragment.shader_defs.push("ALPHA_CUTOUT".into());
+            }
+            if key.bind_group_data.texture_array {
+                fragment.shader_defs.push("TEXTURE_ARRAY".into());
+            }
+        }
         Ok(())
     }
 }
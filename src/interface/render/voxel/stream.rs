@@ -0,0 +1,173 @@
+/// Spawning and despawning chunks around the camera, saved chunks are read in the background
+use bevy::{
+    tasks::{block_on, futures_lite::future, IoTaskPool, Task},
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    config::GameSettings,
+    interface::{
+        constants::{CHUNK_DESPAWN_BUDGET, CHUNK_SPAWN_BUDGET, VOXEL_SIZE},
+        render::camera::MainCamera,
+    },
+    prelude::*,
+    voxel::{
+        chunks::{
+            chunk::Chunk,
+            load_chunks,
+            map::ChunkMap,
+            region::{region_pos, RegionChunks, RegionError, WorldDir},
+            save::ChunkSaver,
+        },
+        generator::WorldGen,
+    },
+};
+
+use super::chunk::{chunk_translation, free_section_meshes, RenderOfChunk, SectionOfChunk};
+
+pub struct ChunkStreamPlugin;
+
+impl Plugin for ChunkStreamPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChunkLoads>().add_systems(
+            Update,
+            stream_chunks.run_if(
+                in_state(GameState::Play)
+                    .and_then(resource_exists::<WorldGen>)
+                    .and_then(resource_exists::<WorldDir>),
+            ),
+        );
+    }
+}
+
+/// Chunk loads running on the [`IoTaskPool`], one per region and frame
+#[derive(Resource, Default)]
+pub(super) struct ChunkLoads(Vec<RegionLoad>);
+
+struct RegionLoad {
+    positions: Vec<IVec2>,
+    task: Task<Result<RegionChunks, RegionError>>,
+}
+
+impl ChunkLoads {
+    fn contains(&self, pos: IVec2) -> bool {
+        self.0.iter().any(|load| load.positions.contains(&pos))
+    }
+    /// Chunks of finished loads, `None` for chunks to generate
+    fn finished(&mut self) -> Vec<(IVec2, Option<Chunk>)> {
+        let mut finished = Vec::new();
+        self.0.retain_mut(|load| {
+            let Some(result) = block_on(future::poll_once(&mut load.task)) else {
+                return true;
+            };
+            match result {
+                Ok(chunks) => finished.extend(chunks.into_iter().map(|(pos, chunk)| {
+                    let chunk = chunk.unwrap_or_else(|err| {
+                        error!("Failed to load chunk {pos}: {err}");
+                        None
+                    });
+                    (pos, chunk)
+                })),
+                Err(err) => {
+                    error!("Failed to load chunks {:?}: {err}", load.positions);
+                    finished.extend(load.positions.iter().map(|pos| (*pos, None)));
+                }
+            }
+            false
+        });
+        finished
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) fn stream_chunks(
+    mut commands: Commands,
+    map: Res<ChunkMap>,
+    generator: Res<WorldGen>,
+    world: Res<WorldDir>,
+    mut saver: ResMut<ChunkSaver>,
+    mut loads: ResMut<ChunkLoads>,
+    chunks: Query<&Chunk>,
+    settings: Res<GameSettings>,
+    cam: Query<&GlobalTransform, With<MainCamera>>,
+    renders: Query<&RenderOfChunk>,
+    meshes: Query<&Handle<Mesh>>,
+    sections: Query<&SectionOfChunk>,
+    mut meshes_assets: ResMut<Assets<Mesh>>,
+) {
+    let Ok(cam) = cam.get_single() else {
+        return;
+    };
+    let center = ChunkMap::chunk_pos((cam.translation() / VOXEL_SIZE).floor().as_ivec3());
+    let radius = settings.graphics.render_distance as i32;
+
+    // One chunk of hysteresis so chunks on the edge don't flicker
+    let unload_radius_sq = (radius + 1) * (radius + 1);
+    for (_, entity) in map
+        .iter()
+        .filter(|(pos, _)| (*pos - center).length_squared() > unload_radius_sq)
+        .take(CHUNK_DESPAWN_BUDGET)
+    {
+        if let Ok(chunk) = chunks.get(entity) {
+            if chunk.is_dirty() {
+                saver.push(chunk.clone());
+            }
+        }
+        if let Ok(render) = renders.get(entity) {
+            for section in render.sections.iter().flatten() {
+                free_section_meshes(*section, &sections, &meshes, &mut meshes_assets);
+            }
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let mut spawn = |chunk: Chunk| {
+        let translation = chunk_translation(chunk.pos);
+        commands.spawn((
+            chunk,
+            RenderOfChunk::default(),
+            SpatialBundle::from_transform(Transform::from_translation(translation)),
+        ));
+    };
+    // Spawns are deferred, the map only learns about them next frame
+    let mut spawned = HashSet::new();
+    for (pos, chunk) in loads.finished() {
+        // Left the render distance while loading
+        if (pos - center).length_squared() > unload_radius_sq || map.contains(pos) {
+            continue;
+        }
+        spawn(chunk.unwrap_or_else(|| generator.generate(pos)));
+        spawned.insert(pos);
+    }
+
+    let mut missing = Vec::new();
+    for x in -radius..=radius {
+        for z in -radius..=radius {
+            let offset = IVec2::new(x, z);
+            let pos = center + offset;
+            if offset.length_squared() <= radius * radius
+                && !map.contains(pos)
+                && !loads.contains(pos)
+                && !spawned.contains(&pos)
+            {
+                missing.push(pos);
+            }
+        }
+    }
+    missing.sort_by_key(|pos| (*pos - center).length_squared());
+    let mut regions: HashMap<IVec2, Vec<IVec2>> = HashMap::new();
+    for pos in missing.into_iter().take(CHUNK_SPAWN_BUDGET) {
+        // An unloaded chunk may still be waiting for its write
+        match saver.unsaved(pos) {
+            Some(chunk) => spawn(chunk),
+            None => regions.entry(region_pos(pos)).or_default().push(pos),
+        }
+    }
+    for (region, positions) in regions {
+        let world = world.0.clone();
+        let task_positions = positions.clone();
+        let task =
+            IoTaskPool::get().spawn(async move { load_chunks(&world, region, &task_positions) });
+        loads.0.push(RegionLoad { positions, task });
+    }
+}
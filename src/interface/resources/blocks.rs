@@ -1,13 +1,20 @@
-use crate::prelude::*;
+use crate::{
+    prelude::*,
+    voxel::blocks::{registry::namespaced, BlockProperties, BlockState, StateProperty},
+};
 use bevy::asset::LoadDirectError;
-use bevy::utils::hashbrown::HashMap;
+use bevy::utils::hashbrown::{HashMap, HashSet};
 use bevy::utils::thiserror;
 use bevy::{
     asset::{io::Reader, ron, AssetLoader, AsyncReadExt, LoadContext},
     reflect::TypePath,
     utils::BoxedFuture,
 };
-use serde::Deserialize;
+use serde::{
+    de::{self, value::MapAccessDeserializer, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use std::{fmt, marker::PhantomData, path::Path};
 use thiserror::Error;
 
 pub struct BlocksTypesLoaderPlugin;
@@ -21,31 +28,315 @@ impl Plugin for BlocksTypesLoaderPlugin {
 
 #[derive(TypePath, Debug, Deserialize, Clone)]
 pub enum UnMeshedBlockType {
-    Block { faces: BlockFaces },
+    Block {
+        faces: BlockFaces,
+        #[serde(default)]
+        properties: BlockProperties,
+    },
+    /// Two crossed quads (flowers, tall grass)
+    Cross {
+        texture: String, // Img
+        #[serde(default)]
+        tint: Tint,
+        #[serde(default)]
+        properties: BlockProperties,
+    },
+    /// Bottom half of a block
+    Slab {
+        faces: BlockFaces,
+        #[serde(default)]
+        properties: BlockProperties,
+    },
+    /// Bottom half with a step on the back (+Z) half
+    Stairs {
+        faces: BlockFaces,
+        #[serde(default)]
+        properties: BlockProperties,
+    },
+    /// Any boxes, faces textures are cropped to the box
+    Model {
+        faces: BlockFaces,
+        boxes: Vec<ModelBox>,
+        #[serde(default)]
+        properties: BlockProperties,
+    },
+}
+
+impl UnMeshedBlockType {
+    pub fn properties(&self) -> BlockProperties {
+        match self {
+            Self::Block { properties, .. }
+            | Self::Cross { properties, .. }
+            | Self::Slab { properties, .. }
+            | Self::Stairs { properties, .. }
+            | Self::Model { properties, .. } => *properties,
+        }
+    }
+}
+
+impl UnMeshedBlockType {
+    /// Image keys the type references
+    pub fn images(&self) -> Vec<&str> {
+        match self {
+            Self::Cross { texture, .. } => vec![texture.as_str()],
+            Self::Block { faces, .. }
+            | Self::Slab { faces, .. }
+            | Self::Stairs { faces, .. }
+            | Self::Model { faces, .. } => faces.images().to_vec(),
+        }
+    }
+    /// Image references resolved in the pack `namespace`
+    fn namespaced(self, namespace: &str) -> Self {
+        match self {
+            Self::Block { faces, properties } => Self::Block {
+                faces: faces.namespaced(namespace),
+                properties,
+            },
+            Self::Cross {
+                texture,
+                tint,
+                properties,
+            } => Self::Cross {
+                texture: namespaced(namespace, &texture),
+                tint,
+                properties,
+            },
+            Self::Slab { faces, properties } => Self::Slab {
+                faces: faces.namespaced(namespace),
+                properties,
+            },
+            Self::Stairs { faces, properties } => Self::Stairs {
+                faces: faces.namespaced(namespace),
+                properties,
+            },
+            Self::Model {
+                faces,
+                boxes,
+                properties,
+            } => Self::Model {
+                faces: faces.namespaced(namespace),
+                boxes,
+                properties,
+            },
+        }
+    }
+}
+
+/// Box of a model in block pixels, the block is `[0, 0, 0]..[16, 16, 16]`
+#[derive(TypePath, Debug, Deserialize, Clone, Copy)]
+pub struct ModelBox {
+    pub from: [f32; 3],
+    pub to: [f32; 3],
 }
 
 #[derive(TypePath, Debug, Deserialize, Clone)]
 pub struct BlockFaces {
-    pub top: String, // Img
-    pub bottom: String,
-    pub left: String,
-    pub right: String,
-    pub forward: String,
-    pub backward: String,
+    pub top: BlockFace,
+    pub bottom: BlockFace,
+    pub left: BlockFace,
+    pub right: BlockFace,
+    pub forward: BlockFace,
+    pub backward: BlockFace,
+}
+
+impl BlockFaces {
+    /// Same face on every side
+    pub fn all(face: BlockFace) -> Self {
+        Self {
+            top: face.clone(),
+            bottom: face.clone(),
+            left: face.clone(),
+            right: face.clone(),
+            forward: face.clone(),
+            backward: face,
+        }
+    }
+    fn images(&self) -> [&str; 6] {
+        [
+            &self.top.texture,
+            &self.bottom.texture,
+            &self.left.texture,
+            &self.right.texture,
+            &self.forward.texture,
+            &self.backward.texture,
+        ]
+    }
+    fn namespaced(self, namespace: &str) -> Self {
+        Self {
+            top: self.top.namespaced(namespace),
+            bottom: self.bottom.namespaced(namespace),
+            left: self.left.namespaced(namespace),
+            right: self.right.namespaced(namespace),
+            forward: self.forward.namespaced(namespace),
+            backward: self.backward.namespaced(namespace),
+        }
+    }
+}
+
+/// Face image, written as the image key or as `(texture: "grass_up", tint: Grass)`
+#[derive(TypePath, Debug, Clone)]
+pub struct BlockFace {
+    pub texture: String, // Img
+    pub tint: Tint,
+}
+
+impl BlockFace {
+    pub fn untinted(texture: &str) -> Self {
+        Self {
+            texture: texture.to_string(),
+            tint: Tint::None,
+        }
+    }
+    fn namespaced(self, namespace: &str) -> Self {
+        Self {
+            texture: namespaced(namespace, &self.texture),
+            tint: self.tint,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockFace {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        /// Long form of [`BlockFace`]
+        #[derive(Deserialize)]
+        struct TintedFace {
+            texture: String,
+            #[serde(default)]
+            tint: Tint,
+        }
+        struct FaceVisitor;
+        impl<'de> Visitor<'de> for FaceVisitor {
+            type Value = BlockFace;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an image key or a face with a tint")
+            }
+            fn visit_str<E: de::Error>(self, texture: &str) -> Result<Self::Value, E> {
+                Ok(BlockFace::untinted(texture))
+            }
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                // Not an untagged enum: buffered content can't hold RON enums like `tint: Grass`
+                let face = TintedFace::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(BlockFace {
+                    texture: face.texture,
+                    tint: face.tint,
+                })
+            }
+        }
+        deserializer.deserialize_any(FaceVisitor)
+    }
+}
+
+/// Color the face texture is multiplied with, meant for grayscale images
+#[derive(TypePath, Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum Tint {
+    #[default]
+    None,
+    /// Fixed sRGB color, components in `0..=1`
+    Rgb(f32, f32, f32),
+    /// Grass color of the climate, blended between blocks
+    Grass,
+    /// Leaves color of the climate, blended between blocks
+    Foliage,
+    /// Fixed sRGB color picked by a block state property, `off` while its [`BlockState::value`]
+    /// is 0 and `on` otherwise, e.g. `State(property: Open, off: (1, 1, 1), on: (1, 0.8, 0.6))`
+    State {
+        property: StateProperty,
+        off: (f32, f32, f32),
+        on: (f32, f32, f32),
+    },
+}
+
+impl Tint {
+    /// Tint of a block in `state`, state tints become fixed colors
+    pub fn in_state(self, state: BlockState) -> Self {
+        match self {
+            Self::State { property, off, on } => {
+                let (r, g, b) = if state.value(property) == 0 { off } else { on };
+                Self::Rgb(r, g, b)
+            }
+            tint => tint,
+        }
+    }
 }
 
-#[derive(Asset, TypePath, Debug)]
-#[allow(dead_code)]
+/// Image of a pack: a path, or a vertical strip of square frames
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ImageSource {
+    Path(String),
+    Animation {
+        path: String,
+        /// Taken from the strip size when omitted
+        #[serde(default)]
+        frames: Option<u32>,
+        /// Seconds per frame
+        #[serde(default = "default_frame_time")]
+        frame_time: f32,
+        /// Blend each frame into the next one
+        #[serde(default)]
+        interpolate: bool,
+    },
+}
+
+fn default_frame_time() -> f32 {
+    0.1
+}
+
+impl ImageSource {
+    fn path(&self) -> &str {
+        match self {
+            Self::Path(path) | Self::Animation { path, .. } => path,
+        }
+    }
+}
+
+/// Frames of an animated image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageAnimation {
+    pub frames: u32,
+    pub frame_time: f32,
+    pub interpolate: bool,
+}
+
+/// Block pack, names of images and types are prefixed with the pack namespace (`core:grass`)
+#[derive(Asset, TypePath, Debug, Default)]
 pub struct BlockTypesAsset {
+    /// Whole images, animated ones are strips of frames from top to bottom
     pub images: HashMap<String, Image>,
+    pub animations: HashMap<String, ImageAnimation>,
     pub types: HashMap<String, UnMeshedBlockType>,
+    /// Non-fatal issues found while loading
+    pub warnings: Vec<BlockTypesWarning>,
 }
 
-#[derive(Asset, TypePath, Debug, Deserialize)]
-#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
 pub struct PreBlockTypesAsset {
-    pub images: HashMap<String, String>,
-    pub types: HashMap<String, UnMeshedBlockType>,
+    pub images: Entries<ImageSource>,
+    pub types: Entries<UnMeshedBlockType>,
+}
+
+/// RON map kept in file order with repeated keys, which a `HashMap` would silently drop
+#[derive(Debug)]
+pub struct Entries<V>(pub Vec<(String, V)>);
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for Entries<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntriesVisitor<V>(PhantomData<V>);
+        impl<'de, V: Deserialize<'de>> Visitor<'de> for EntriesVisitor<V> {
+            type Value = Entries<V>;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map")
+            }
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut entries = Vec::new();
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Entries(entries))
+            }
+        }
+        deserializer.deserialize_map(EntriesVisitor(PhantomData))
+    }
 }
 
 #[derive(Default)]
@@ -60,10 +351,59 @@ pub enum BlockTypesAssetLoaderError {
     /// A [RON](ron) Error
     #[error("Could not parse RON: {0}")]
     RonSpannedError(#[from] ron::error::SpannedError),
-    #[error("Could not load image")]
-    LoadImageError(#[from] LoadDirectError),
-    #[error("Invalid image type")]
-    InvalidImageType,
+    #[error("{file}: could not load image `{name}` from `{path}`: {source}")]
+    UnreadableImage {
+        file: String,
+        name: String,
+        path: String,
+        source: Box<LoadDirectError>,
+    },
+    #[error("{file}: `{path}` of image `{name}` is not an image")]
+    InvalidImageType {
+        file: String,
+        name: String,
+        path: String,
+    },
+    #[error("{file}: image `{name}` is {width}x{height}, block textures must be square")]
+    NonSquareImage {
+        file: String,
+        name: String,
+        width: u32,
+        height: u32,
+    },
+    #[error("{file}: image `{name}` is {width}x{height}, which is not a strip of {frames} square frames")]
+    InvalidAnimation {
+        file: String,
+        name: String,
+        width: u32,
+        height: u32,
+        frames: u32,
+    },
+    #[error("{file}: image `{name}` is defined more than once")]
+    DuplicateImage { file: String, name: String },
+    #[error("{file}: block type `{name}` is defined more than once")]
+    DuplicateType { file: String, name: String },
+    #[error("{file}: block type `{block}` references unknown image `{image}`")]
+    UnknownImage {
+        file: String,
+        block: String,
+        image: String,
+    },
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum BlockTypesWarning {
+    #[error("{file}: image `{name}` is not used by any block type")]
+    UnusedImage { file: String, name: String },
+    #[error("{file}: image `{name}` is {size}px while `{first}` is {expected}px")]
+    MismatchedImageSize {
+        file: String,
+        name: String,
+        size: u32,
+        first: String,
+        expected: u32,
+    },
 }
 
 impl AssetLoader for BlockTypesLoader {
@@ -80,18 +420,94 @@ impl AssetLoader for BlockTypesLoader {
             let mut bytes = Vec::new();
             reader.read_to_end(&mut bytes).await?;
             let custom_asset = ron::de::from_bytes::<PreBlockTypesAsset>(&bytes)?;
+            let file = load_context.path().display().to_string();
+            let namespace = pack_namespace(load_context.path());
+            let mut warnings = validate(&file, &custom_asset)?;
+
             let mut images = HashMap::new();
-            for (name, file) in custom_asset.images {
+            let mut animations = HashMap::new();
+            // First image sets the texture size of the pack
+            let mut first_size: Option<(String, u32)> = None;
+            for (name, source) in custom_asset.images.0 {
+                let path = source.path().to_string();
                 let img = load_context
-                    .load_direct(file)
-                    .await?
+                    .load_direct(path.clone())
+                    .await
+                    .map_err(|source| BlockTypesAssetLoaderError::UnreadableImage {
+                        file: file.clone(),
+                        name: name.clone(),
+                        path: path.clone(),
+                        source: Box::new(source),
+                    })?
                     .take::<Image>()
-                    .ok_or(BlockTypesAssetLoaderError::InvalidImageType)?;
-                images.insert(name, img);
+                    .ok_or_else(|| BlockTypesAssetLoaderError::InvalidImageType {
+                        file: file.clone(),
+                        name: name.clone(),
+                        path,
+                    })?;
+                let size = img.size();
+                match source {
+                    ImageSource::Path(_) if size.x != size.y => {
+                        return Err(BlockTypesAssetLoaderError::NonSquareImage {
+                            file,
+                            name,
+                            width: size.x,
+                            height: size.y,
+                        });
+                    }
+                    ImageSource::Path(_) => {}
+                    ImageSource::Animation {
+                        frames,
+                        frame_time,
+                        interpolate,
+                        ..
+                    } => {
+                        let frames = frames.unwrap_or(size.y / size.x.max(1));
+                        if frames == 0 || size.y != size.x * frames {
+                            return Err(BlockTypesAssetLoaderError::InvalidAnimation {
+                                file,
+                                name,
+                                width: size.x,
+                                height: size.y,
+                                frames,
+                            });
+                        }
+                        animations.insert(
+                            namespaced(&namespace, &name),
+                            ImageAnimation {
+                                frames,
+                                frame_time,
+                                interpolate,
+                            },
+                        );
+                    }
+                }
+                match &first_size {
+                    Some((first, expected)) if *expected != size.x => {
+                        warnings.push(BlockTypesWarning::MismatchedImageSize {
+                            file: file.clone(),
+                            name: name.clone(),
+                            size: size.x,
+                            first: first.clone(),
+                            expected: *expected,
+                        });
+                    }
+                    Some(_) => {}
+                    None => first_size = Some((name.clone(), size.x)),
+                }
+                images.insert(namespaced(&namespace, &name), img);
             }
+            let types = custom_asset
+                .types
+                .0
+                .into_iter()
+                .map(|(name, type_)| (namespaced(&namespace, &name), type_.namespaced(&namespace)))
+                .collect();
             Ok(BlockTypesAsset {
                 images,
-                types: custom_asset.types,
+                animations,
+                types,
+                warnings,
             })
         })
     }
@@ -100,3 +516,82 @@ impl AssetLoader for BlockTypesLoader {
         &["btypes.ron", "ron.btypes"]
     }
 }
+
+/// Namespace of the pack is its file name without extensions, `core.btypes.ron` is `core`
+fn pack_namespace(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    name.split('.').next().unwrap_or_default().to_string()
+}
+
+/// Checks names and image references of the file, returns non-fatal issues.
+/// References to other packs (`other:image`) are checked once packs are merged
+fn validate(
+    file: &str,
+    asset: &PreBlockTypesAsset,
+) -> Result<Vec<BlockTypesWarning>, BlockTypesAssetLoaderError> {
+    let mut images = HashSet::new();
+    for (name, _) in &asset.images.0 {
+        if !images.insert(name.as_str()) {
+            return Err(BlockTypesAssetLoaderError::DuplicateImage {
+                file: file.to_string(),
+                name: name.clone(),
+            });
+        }
+    }
+    let mut types = HashSet::new();
+    let mut used = HashSet::new();
+    for (name, type_) in &asset.types.0 {
+        if !types.insert(name.as_str()) {
+            return Err(BlockTypesAssetLoaderError::DuplicateType {
+                file: file.to_string(),
+                name: name.clone(),
+            });
+        }
+        for image in type_.images() {
+            if image.contains(':') {
+                continue;
+            }
+            if !images.contains(image) {
+                return Err(BlockTypesAssetLoaderError::UnknownImage {
+                    file: file.to_string(),
+                    block: name.clone(),
+                    image: image.to_string(),
+                });
+            }
+            used.insert(image);
+        }
+    }
+    Ok(asset
+        .images
+        .0
+        .iter()
+        .filter(|(name, _)| !used.contains(name.as_str()))
+        .map(|(name, _)| BlockTypesWarning::UnusedImage {
+            file: file.to_string(),
+            name: name.clone(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_tint_follows_block_state() {
+        let face: BlockFace = ron::de::from_str(
+            "(texture: \"door\", tint: State(property: Open, off: (1, 1, 1), on: (0.5, 0.5, 0.5)))",
+        )
+        .unwrap();
+        let closed = BlockState::default();
+        assert_eq!(face.tint.in_state(closed), Tint::Rgb(1., 1., 1.));
+        assert_eq!(
+            face.tint.in_state(closed.with_open(true)),
+            Tint::Rgb(0.5, 0.5, 0.5)
+        );
+        assert_eq!(Tint::Grass.in_state(closed.with_open(true)), Tint::Grass);
+    }
+}
@@ -5,7 +5,8 @@ use crate::prelude::*;
 pub struct EmbeddedPlugin;
 //pub const EMBEDDED_PATH: &str = "embedded://rustcraft/resources/";
 pub const UNKNOWN_TEXTURE_PATH: &str = "rustcraft/../../../assets/textures/unknown.png";
-pub const UNKNOWN_TEXTURE_SIZE: (u32, u32) = (16, 16);
+/// The same texture for code that needs its pixels before the asset is loaded
+pub const UNKNOWN_TEXTURE_BYTES: &[u8] = include_bytes!("../../../assets/textures/unknown.png");
 
 impl Plugin for EmbeddedPlugin {
     fn build(&self, app: &mut App) {
@@ -3,7 +3,7 @@
 pub use os::gen_app;
 pub use os::OSType;
 //----------------------
-//mod config; TODO!
+mod config;
 mod constants;
 mod debug;
 mod interface;
@@ -30,7 +30,8 @@ pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((VoxelPlugin, InterfacePlugin))
+        app.insert_resource(config::load())
+            .add_plugins((VoxelPlugin, InterfacePlugin))
             .init_state::<GameState>();
         #[cfg(debug_assertions)]
         {
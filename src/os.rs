@@ -6,6 +6,7 @@ use bevy::window::PrimaryWindow;
 use bevy::winit::WinitWindows;
 use bevy::DefaultPlugins;
 use std::io::Cursor;
+use std::time::Duration;
 use winit::window::Icon;
 
 use bevy::window::WindowMode;
@@ -24,7 +25,13 @@ pub fn gen_app(os: OSType) -> App {
     use bevy::asset::io::{file::FileAssetReader, AssetSource};
     app.register_asset_source(
         "asset",
-        AssetSource::build().with_reader(|| Box::new(FileAssetReader::new("assets"))),
+        AssetSource::build()
+            .with_reader(|| Box::new(FileAssetReader::new("assets")))
+            // Only watches with the `file_watcher` feature (enabled by `dev`)
+            .with_watcher(AssetSource::get_default_watcher(
+                "assets".to_string(),
+                Duration::from_millis(300),
+            )),
     );
     match os {
         OSType::Windows => {
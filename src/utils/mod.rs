@@ -1 +1,17 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
 
+/// Writes into a temporary file next to `path` and renames it over `path`,
+/// so a crash never leaves a half written file
+pub fn write_atomic(path: &Path, bytes: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(bytes.as_ref())?;
+    // Flushed before the rename, so a crash can't leave an empty file in place of the old one
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
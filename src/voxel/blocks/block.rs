@@ -1,16 +1,51 @@
-#[derive(Clone, Copy, Debug, Default)]
+use super::BlockState;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum Block {
     #[default]
     Air,
-    Solid(BlockId),
+    Solid(BlockId, BlockState),
 }
 impl Block {
+    /// Solid block in its default state
+    pub fn solid(id: BlockId) -> Self {
+        Self::Solid(id, BlockState::default())
+    }
     pub fn is_solid(&self) -> bool {
+        matches!(self, Self::Solid(..))
+    }
+    pub fn id(&self) -> Option<BlockId> {
         match self {
-            Self::Solid(_) => true,
-            _ => false,
+            Self::Air => None,
+            Self::Solid(id, _) => Some(*id),
+        }
+    }
+    /// Encoding used in saves: air is 0, solid blocks are `id + 1` in the low [`RAW_ID_BITS`]
+    /// and the state above them
+    pub fn to_raw(self) -> u32 {
+        match self {
+            Self::Air => 0,
+            Self::Solid(BlockId(id), BlockState(state)) => {
+                debug_assert!(id <= MAX_BLOCK_ID, "Block id {id} does not fit in saves");
+                ((id + 1) & RAW_ID_MASK) | ((state as u32) << RAW_ID_BITS)
+            }
+        }
+    }
+    pub fn from_raw(raw: u32) -> Self {
+        match raw & RAW_ID_MASK {
+            0 => Self::Air,
+            id => Self::Solid(BlockId(id - 1), BlockState((raw >> RAW_ID_BITS) as u8)),
         }
     }
 }
-#[derive(Clone, PartialEq, Eq, Hash, Copy, Debug, Default)]
+
+const RAW_ID_BITS: u32 = 24;
+const RAW_ID_MASK: u32 = (1 << RAW_ID_BITS) - 1;
+/// Biggest id [`Block::to_raw`] can encode
+pub const MAX_BLOCK_ID: u32 = RAW_ID_MASK - 1;
+
+#[derive(
+    Clone, PartialEq, Eq, Hash, Copy, Debug, Default, serde::Serialize, serde::Deserialize,
+)]
+#[serde(transparent)]
 pub struct BlockId(pub u32);
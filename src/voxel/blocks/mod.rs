@@ -1,6 +1,13 @@
 mod block;
+mod properties;
+pub mod registry;
+mod side;
+mod state;
 
 pub use block::*;
+pub use properties::{BlockProperties, Transparency};
+pub use side::Side;
+pub use state::{BlockState, StateProperties, StateProperty};
 
 use crate::prelude::*;
 
@@ -0,0 +1,72 @@
+use serde::Deserialize;
+
+use super::StateProperties;
+
+/// Gameplay and rendering properties of a block type, every field has a default
+/// so `properties` can be omitted or partial in `.btypes.ron`
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BlockProperties {
+    /// How the block is drawn, neighbour faces behind not opaque blocks are visible.
+    /// Older packs set `transparent: true`, that is drawn as `Cutout`
+    #[serde(alias = "transparent")]
+    pub transparency: Transparency,
+    /// Has a collision box
+    pub collidable: bool,
+    /// Can be broken by the player
+    pub breakable: bool,
+    /// Emitted light level, 0..=15
+    pub light: u8,
+    /// Time to break relative to dirt
+    pub hardness: f32,
+    /// Block states the type uses, e.g. `states: [Facing, Half]`
+    pub states: StateProperties,
+}
+
+impl Default for BlockProperties {
+    fn default() -> Self {
+        Self {
+            transparency: Transparency::Opaque,
+            collidable: true,
+            breakable: true,
+            light: 0,
+            hardness: 1.,
+            states: StateProperties::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Transparency {
+    #[default]
+    #[serde(alias = "false")]
+    Opaque,
+    /// Texels are either drawn or discarded by alpha test (leaves)
+    #[serde(alias = "true")]
+    Cutout,
+    /// Blended with what is behind (glass, water)
+    Translucent,
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::ron;
+
+    use super::*;
+
+    #[test]
+    fn transparent_bool_is_read_as_transparency() {
+        let parse = |text: &str| {
+            ron::de::from_str::<BlockProperties>(text)
+                .unwrap()
+                .transparency
+        };
+        assert_eq!(parse("(transparent: true)"), Transparency::Cutout);
+        assert_eq!(parse("(transparent: false)"), Transparency::Opaque);
+        assert_eq!(
+            parse("(transparency: Translucent)"),
+            Transparency::Translucent
+        );
+        assert_eq!(parse("()"), Transparency::Opaque);
+    }
+}
@@ -0,0 +1,121 @@
+/// Name to [`BlockId`] mapping saved with each world, so ids in saved chunks stay valid
+/// across sessions and block types asset edits
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::{asset::ron, utils::thiserror};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{prelude::*, utils::write_atomic};
+
+use super::{BlockId, MAX_BLOCK_ID};
+
+const REGISTRY_FILE: &str = "blocks.ron";
+/// Namespace of the built-in pack, names saved before packs had namespaces belong to it
+pub const CORE_NAMESPACE: &str = "core";
+/// Built-in type for ids without a type, the only name without a namespace
+pub const UNKNOWN_BLOCK: &str = "unknown";
+/// First id given out, matches the first id the block storage gives out
+const FIRST_ID: u32 = 1;
+
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BlockRegistry {
+    ids: BTreeMap<String, BlockId>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum BlockRegistryError {
+    #[error("Block registry io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Could not parse block registry: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("Could not write block registry: {0}")]
+    Write(#[from] ron::Error),
+}
+
+impl BlockRegistry {
+    /// Registry of the world, empty if the world has none yet
+    pub fn load(world: &Path) -> Result<Self, BlockRegistryError> {
+        match fs::read(registry_path(world)) {
+            Ok(bytes) => {
+                let registry: Self = ron::de::from_bytes(&bytes)?;
+                Ok(registry.with_namespaces().without_invalid_ids())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+    pub fn save(&self, world: &Path) -> Result<(), BlockRegistryError> {
+        fs::create_dir_all(world)?;
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        write_atomic(&registry_path(world), text)?;
+        Ok(())
+    }
+    pub fn get(&self, name: &str) -> Option<BlockId> {
+        self.ids.get(name).copied()
+    }
+    /// Id of the name, new names are appended after the biggest known id
+    pub fn get_or_insert(&mut self, name: &str) -> BlockId {
+        if let Some(id) = self.get(name) {
+            return id;
+        }
+        let id = self.next_id();
+        assert!(id.0 <= MAX_BLOCK_ID, "Block ids are exhausted");
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+    /// Id that is not used by any name
+    pub fn next_id(&self) -> BlockId {
+        BlockId(
+            self.ids
+                .values()
+                .map(|id| id.0 + 1)
+                .max()
+                .unwrap_or(FIRST_ID),
+        )
+    }
+}
+
+impl BlockRegistry {
+    /// Ids too big for saved chunks were never stored in them, their names get new ids
+    fn without_invalid_ids(mut self) -> Self {
+        self.ids.retain(|name, id| {
+            let valid = id.0 <= MAX_BLOCK_ID;
+            if !valid {
+                error!("Dropping block {name} with invalid id {}", id.0);
+            }
+            valid
+        });
+        self
+    }
+    /// Names from before namespaces are moved to the core pack
+    fn with_namespaces(self) -> Self {
+        let ids = self
+            .ids
+            .into_iter()
+            .map(|(name, id)| match name.as_str() {
+                UNKNOWN_BLOCK => (name, id),
+                _ => (namespaced(CORE_NAMESPACE, &name), id),
+            })
+            .collect();
+        Self { ids }
+    }
+}
+
+/// `name` in the `namespace`, names that already have one are kept
+pub fn namespaced(namespace: &str, name: &str) -> String {
+    if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("{namespace}:{name}")
+    }
+}
+
+fn registry_path(world: &Path) -> PathBuf {
+    world.join(REGISTRY_FILE)
+}
@@ -0,0 +1,58 @@
+use crate::prelude::*;
+
+/// Side of a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,    // -X
+    Right,   // +X
+    Bottom,  // -Y
+    Top,     // +Y
+    Forward, // -Z
+    Back,    // +Z
+}
+
+impl Side {
+    pub const ALL: [Side; 6] = [
+        Side::Left,
+        Side::Right,
+        Side::Bottom,
+        Side::Top,
+        Side::Forward,
+        Side::Back,
+    ];
+    /// Axis index (x = 0, y = 1, z = 2) and whether the side looks to its positive end
+    pub fn axis(self) -> (usize, bool) {
+        match self {
+            Self::Left => (0, false),
+            Self::Right => (0, true),
+            Self::Bottom => (1, false),
+            Self::Top => (1, true),
+            Self::Forward => (2, false),
+            Self::Back => (2, true),
+        }
+    }
+    pub fn from_axis(axis: usize, positive: bool) -> Self {
+        Self::ALL[axis * 2 + positive as usize]
+    }
+    pub fn opposite(self) -> Self {
+        let (axis, positive) = self.axis();
+        Self::from_axis(axis, !positive)
+    }
+    pub fn normal(self) -> IVec3 {
+        let (axis, positive) = self.axis();
+        let mut normal = IVec3::ZERO;
+        normal[axis] = if positive { 1 } else { -1 };
+        normal
+    }
+    pub fn bit(self) -> u8 {
+        1 << self as u8
+    }
+    pub fn from_normal(normal: IVec3) -> Option<Self> {
+        Self::ALL.into_iter().find(|side| side.normal() == normal)
+    }
+    /// Side after rotating/mirroring the block by `m` (see [`BlockState::rotation`](super::BlockState::rotation))
+    pub fn transformed(self, m: Mat3) -> Self {
+        let normal = (m * self.normal().as_vec3()).round().as_ivec3();
+        Self::from_normal(normal).unwrap_or(self)
+    }
+}
@@ -0,0 +1,135 @@
+/// Block states: small per block values (orientation, half, open) stored next to the [`BlockId`](super::BlockId)
+///
+/// Bits: facing 0..=1, axis 2..=3, half 4, open 5.
+/// Which of them a block type uses is declared in its properties (`states: [Facing, Half]`)
+use serde::Deserialize;
+
+use crate::prelude::*;
+
+use super::Side;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockState(pub u8);
+
+/// State property a block type can declare
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum StateProperty {
+    /// Horizontal direction the model's back (+Z) side looks to
+    Facing,
+    /// Axis the model's Y axis is turned to (logs)
+    Axis,
+    /// Model is upside down on the top half
+    Half,
+    /// Toggled by using the block instead of placing on it (doors)
+    Open,
+}
+
+impl StateProperty {
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Set of declared [`StateProperty`]s
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "Vec<StateProperty>")]
+pub struct StateProperties(u8);
+
+impl From<Vec<StateProperty>> for StateProperties {
+    fn from(properties: Vec<StateProperty>) -> Self {
+        Self(properties.into_iter().fold(0, |bits, p| bits | p.bit()))
+    }
+}
+
+impl StateProperties {
+    pub fn has(self, property: StateProperty) -> bool {
+        self.0 & property.bit() != 0
+    }
+}
+
+/// Horizontal facings in quarter turns around +Y, starting from the model's own facing
+const FACINGS: [Side; 4] = [Side::Back, Side::Right, Side::Forward, Side::Left];
+/// Axes the model's Y axis can be turned to
+const AXES: [usize; 3] = [1, 0, 2];
+
+const FACING_MASK: u8 = 0b11;
+const AXIS_SHIFT: u8 = 2;
+const AXIS_MASK: u8 = 0b11 << AXIS_SHIFT;
+const HALF_BIT: u8 = 1 << 4;
+const OPEN_BIT: u8 = 1 << 5;
+
+impl BlockState {
+    pub fn with_facing(self, facing: Side) -> Self {
+        let index = FACINGS.iter().position(|f| *f == facing).unwrap_or(0) as u8;
+        Self((self.0 & !FACING_MASK) | index)
+    }
+    /// Axis index (x = 0, y = 1, z = 2)
+    pub fn axis(self) -> usize {
+        AXES[(((self.0 & AXIS_MASK) >> AXIS_SHIFT) as usize).min(AXES.len() - 1)]
+    }
+    pub fn with_axis(self, axis: usize) -> Self {
+        let index = AXES.iter().position(|a| *a == axis).unwrap_or(0) as u8;
+        Self((self.0 & !AXIS_MASK) | (index << AXIS_SHIFT))
+    }
+    /// Value of the property: facing or axis index, 1 for a set flag.
+    /// 0 is the state of a block placed without turning it
+    pub fn value(self, property: StateProperty) -> u8 {
+        match property {
+            StateProperty::Facing => self.0 & FACING_MASK,
+            StateProperty::Axis => (self.0 & AXIS_MASK) >> AXIS_SHIFT,
+            StateProperty::Half => self.is_top() as u8,
+            StateProperty::Open => self.is_open() as u8,
+        }
+    }
+    pub fn is_top(self) -> bool {
+        self.0 & HALF_BIT != 0
+    }
+    pub fn with_top(self, top: bool) -> Self {
+        self.with_bit(HALF_BIT, top)
+    }
+    pub fn is_open(self) -> bool {
+        self.0 & OPEN_BIT != 0
+    }
+    pub fn with_open(self, open: bool) -> Self {
+        self.with_bit(OPEN_BIT, open)
+    }
+    fn with_bit(self, bit: u8, value: bool) -> Self {
+        if value {
+            Self(self.0 | bit)
+        } else {
+            Self(self.0 & !bit)
+        }
+    }
+
+    /// Rotation (and mirroring for the top half) of the model around the block center,
+    /// only declared properties count. `None` when the model is not turned
+    pub fn rotation(self, declared: StateProperties) -> Option<Mat3> {
+        let mut m = Mat3::IDENTITY;
+        if declared.has(StateProperty::Half) && self.is_top() {
+            m = Mat3::from_diagonal(Vec3::new(1., -1., 1.));
+        }
+        if declared.has(StateProperty::Axis) {
+            m = match self.axis() {
+                // Y to X
+                0 => Mat3::from_cols(Vec3::NEG_Y, Vec3::X, Vec3::Z) * m,
+                // Y to Z
+                2 => Mat3::from_cols(Vec3::X, Vec3::Z, Vec3::NEG_Y) * m,
+                _ => m,
+            };
+        }
+        let mut turns = 0;
+        if declared.has(StateProperty::Facing) {
+            turns += self.0 & FACING_MASK;
+        }
+        if declared.has(StateProperty::Open) && self.is_open() {
+            turns += 1;
+        }
+        for _ in 0..turns % 4 {
+            m = QUARTER_TURN * m;
+        }
+        (m != Mat3::IDENTITY).then_some(m)
+    }
+}
+
+/// +90° around +Y: back (+Z) goes to right (+X)
+const QUARTER_TURN: Mat3 = Mat3::from_cols(Vec3::NEG_Z, Vec3::Y, Vec3::X);
@@ -1,28 +1,61 @@
+use std::io::{self, Read, Write};
+
 use crate::{prelude::*, voxel::blocks::Block};
 
-#[derive(Component)]
+use super::palette::PalettedBlocks;
+
+/// Column of blocks, `pos` is the chunk coordinate (x, z) in the [`ChunkMap`](super::map::ChunkMap)
+/// Blocks are stored in [`SECTIONS`] sections of [`SECTION_H`] height
+#[derive(Component, Clone)]
 pub struct Chunk {
-    blocks: [[[Block; CHUNK_D]; CHUNK_H]; CHUNK_W],
+    sections: Vec<Section>,
     pub pos: IVec2,
+    /// Edited since the last save
+    dirty: bool,
+}
+
+#[derive(Clone)]
+struct Section {
+    blocks: PalettedBlocks,
+    /// Count of not air blocks, section is empty when it is 0
+    non_air: u16,
 }
 
 impl Chunk {
     pub fn new_air(pos: IVec2) -> Self {
         Self {
-            blocks: [[[Block::Air; CHUNK_D]; CHUNK_H]; CHUNK_W],
+            sections: vec![
+                Section {
+                    blocks: PalettedBlocks::new(CHUNK_W * SECTION_H * CHUNK_D, Block::Air),
+                    non_air: 0,
+                };
+                SECTIONS
+            ],
             pos,
+            dirty: false,
         }
     }
     pub fn get(&self, x: usize, y: usize, z: usize) -> Option<Block> {
         if x < CHUNK_W && z < CHUNK_D && y < CHUNK_H {
-            Some(self.blocks[x][y][z])
+            Some(self.sections[y / SECTION_H].blocks.get(index(x, y, z)))
         } else {
             None
         }
     }
     pub fn set(&mut self, x: usize, y: usize, z: usize, block: Block) {
         if x < CHUNK_W && z < CHUNK_D && y < CHUNK_H {
-            self.blocks[x][y][z] = block;
+            let section = &mut self.sections[y / SECTION_H];
+            let old = section.blocks.get(index(x, y, z));
+            if old == block {
+                return;
+            }
+            self.dirty = true;
+            match (old, block) {
+                (Block::Air, Block::Solid(..)) => section.non_air += 1,
+                (Block::Solid(..), Block::Air) => section.non_air -= 1,
+                _ => {}
+            }
+            section.blocks.set(index(x, y, z), block);
         } else {
             error!("(Chunk set) index out of bounds");
         }
@@ -35,12 +68,12 @@ impl Chunk {
             && y >= 0
             && z >= 0
         {
-            self.blocks[x as usize][y as usize][z as usize] = block;
+            self.set(x as usize, y as usize, z as usize, block);
         } else {
             error!("(Chunk set) index out of bounds");
         }
     }
-    pub fn get_i32(&mut self, x: i32, y: i32, z: i32) -> Option<Block> {
+    pub fn get_i32(&self, x: i32, y: i32, z: i32) -> Option<Block> {
         if x < CHUNK_W as i32
             && z < CHUNK_D as i32
             && y < CHUNK_H as i32
@@ -48,9 +81,47 @@ impl Chunk {
             && y >= 0
             && z >= 0
         {
-            Some(self.blocks[x as usize][y as usize][z as usize])
+            self.get(x as usize, y as usize, z as usize)
         } else {
             None
         }
     }
+    /// Section has only air, it doesn't need a mesh
+    pub fn is_section_empty(&self, section: usize) -> bool {
+        self.sections[section].non_air == 0
+    }
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+    pub fn clear_dirty(&mut self) {
+        self.dirty = false;
+    }
+    /// Writes all sections, position is not stored (it's known from the region)
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        for section in &self.sections {
+            let mut blocks = section.blocks.clone();
+            blocks.compact();
+            blocks.write_to(out)?;
+        }
+        Ok(())
+    }
+    pub fn read_from(pos: IVec2, input: &mut impl Read) -> io::Result<Self> {
+        let len = CHUNK_W * SECTION_H * CHUNK_D;
+        let mut sections = Vec::with_capacity(SECTIONS);
+        for _ in 0..SECTIONS {
+            let blocks = PalettedBlocks::read_from(len, input)?;
+            let non_air = (0..len).filter(|i| blocks.get(*i).is_solid()).count() as u16;
+            sections.push(Section { blocks, non_air });
+        }
+        Ok(Self {
+            sections,
+            pos,
+            dirty: false,
+        })
+    }
+}
+
+/// Index of block inside its section
+fn index(x: usize, y: usize, z: usize) -> usize {
+    (x * SECTION_H + y % SECTION_H) * CHUNK_D + z
 }
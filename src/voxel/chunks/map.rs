@@ -0,0 +1,114 @@
+use bevy::{
+    ecs::query::{QueryData, QueryFilter, WorldQuery},
+    utils::HashMap,
+};
+
+use crate::{prelude::*, voxel::blocks::Block};
+
+use super::chunk::Chunk;
+
+/// World-wide index of chunk entities by chunk coordinate
+#[derive(Resource, Default)]
+pub struct ChunkMap {
+    chunks: HashMap<IVec2, Entity>,
+    positions: HashMap<Entity, IVec2>,
+}
+
+impl ChunkMap {
+    /// Chunk coordinate of the chunk containing the world block `pos`
+    pub fn chunk_pos(pos: IVec3) -> IVec2 {
+        IVec2::new(
+            pos.x.div_euclid(CHUNK_W as i32),
+            pos.z.div_euclid(CHUNK_D as i32),
+        )
+    }
+    /// Position of the world block `pos` inside its chunk
+    pub fn local_pos(pos: IVec3) -> IVec3 {
+        IVec3::new(
+            pos.x.rem_euclid(CHUNK_W as i32),
+            pos.y,
+            pos.z.rem_euclid(CHUNK_D as i32),
+        )
+    }
+
+    pub fn get(&self, pos: IVec2) -> Option<Entity> {
+        self.chunks.get(&pos).copied()
+    }
+    pub fn contains(&self, pos: IVec2) -> bool {
+        self.chunks.contains_key(&pos)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (IVec2, Entity)> + '_ {
+        self.chunks.iter().map(|(pos, e)| (*pos, *e))
+    }
+
+    pub fn insert(&mut self, pos: IVec2, entity: Entity) {
+        if let Some(old) = self.chunks.insert(pos, entity) {
+            if old != entity {
+                warn!("(ChunkMap) chunk {pos} was replaced by another entity");
+                self.positions.remove(&old);
+            }
+        }
+        self.positions.insert(entity, pos);
+    }
+    pub fn remove(&mut self, entity: Entity) -> Option<IVec2> {
+        let pos = self.positions.remove(&entity)?;
+        if self.chunks.get(&pos) == Some(&entity) {
+            self.chunks.remove(&pos);
+        }
+        Some(pos)
+    }
+
+    /// Block at world position, `None` if its chunk is not loaded or `y` is out of the world
+    pub fn get_block<D, F>(&self, pos: IVec3, chunks: &Query<D, F>) -> Option<Block>
+    where
+        D: QueryData,
+        F: QueryFilter,
+        for<'w> D::ReadOnly: WorldQuery<Item<'w> = &'w Chunk>,
+    {
+        let entity = self.get(Self::chunk_pos(pos))?;
+        let local = Self::local_pos(pos);
+        match chunks.get(entity) {
+            Ok(chunk) => chunk.get_i32(local.x, local.y, local.z),
+            Err(err) => {
+                error!("error with query chunk: {err}");
+                None
+            }
+        }
+    }
+    /// Sets block at world position, returns edited chunk entity
+    pub fn set_block<F: QueryFilter>(
+        &self,
+        pos: IVec3,
+        block: Block,
+        chunks: &mut Query<&mut Chunk, F>,
+    ) -> Option<Entity> {
+        let entity = self.get(Self::chunk_pos(pos))?;
+        let local = Self::local_pos(pos);
+        if local.y < 0 || local.y >= CHUNK_H as i32 {
+            return None;
+        }
+        match chunks.get_mut(entity) {
+            Ok(mut chunk) => {
+                chunk.set_i32(local.x, local.y, local.z, block);
+                Some(entity)
+            }
+            Err(err) => {
+                error!("error with query chunk: {err}");
+                None
+            }
+        }
+    }
+}
+
+pub fn update_chunk_map(
+    mut map: ResMut<ChunkMap>,
+    added: Query<(Entity, &Chunk), Added<Chunk>>,
+    mut removed: RemovedComponents<Chunk>,
+) {
+    for entity in removed.read() {
+        map.remove(entity);
+    }
+    for (entity, chunk) in added.iter() {
+        map.insert(chunk.pos, entity);
+    }
+}
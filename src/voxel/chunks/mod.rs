@@ -1 +1,32 @@
 pub mod chunk;
+pub mod map;
+mod palette;
+pub mod region;
+pub mod save;
+
+pub use region::{load_chunks, save_chunks};
+
+use bevy::app::AppExit;
+
+use crate::prelude::*;
+
+pub struct ChunksPlugin;
+
+impl Plugin for ChunksPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<map::ChunkMap>()
+            .init_resource::<save::ChunkSaver>()
+            .add_systems(PreUpdate, map::update_chunk_map)
+            .add_systems(
+                Update,
+                save::autosave.run_if(
+                    in_state(GameState::Play).and_then(resource_exists::<region::WorldDir>),
+                ),
+            )
+            .add_systems(
+                Last,
+                save::save_on_exit
+                    .run_if(on_event::<AppExit>().and_then(resource_exists::<region::WorldDir>)),
+            );
+    }
+}
@@ -0,0 +1,292 @@
+/// Palette compressed block storage
+/// Every distinct block is stored once in the palette, positions keep bit packed palette indices.
+/// Entries are reference counted: unused ones are reused and a storage filled with one block is uniform again
+use std::io::{self, Read, Write};
+
+use crate::voxel::blocks::Block;
+
+#[derive(Clone, Debug)]
+pub struct PalettedBlocks {
+    len: usize,
+    palette: Vec<Block>,
+    /// Positions using each palette entry, entries with 0 are free
+    counts: Vec<u32>,
+    /// Bits per index, 0 while the storage is uniform (single palette entry)
+    bits: u32,
+    data: Vec<u64>,
+}
+
+impl PalettedBlocks {
+    pub fn new(len: usize, block: Block) -> Self {
+        Self {
+            len,
+            palette: vec![block],
+            counts: vec![len as u32],
+            bits: 0,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, i: usize) -> Block {
+        if self.bits == 0 {
+            return self.palette[0];
+        }
+        self.palette[self.get_index(i)]
+    }
+
+    pub fn set(&mut self, i: usize, block: Block) {
+        let old = if self.bits == 0 { 0 } else { self.get_index(i) };
+        if self.palette[old] == block {
+            return;
+        }
+        self.counts[old] -= 1;
+        let free = || self.counts.iter().position(|count| *count == 0);
+        let index = match self.palette.iter().position(|b| *b == block) {
+            Some(index) => index,
+            None => match free() {
+                Some(index) => {
+                    self.palette[index] = block;
+                    index
+                }
+                None => {
+                    self.palette.push(block);
+                    self.counts.push(0);
+                    let needed = bits_for(self.palette.len());
+                    if needed > self.bits {
+                        self.repack(needed);
+                    }
+                    self.palette.len() - 1
+                }
+            },
+        };
+        self.counts[index] += 1;
+        if self.counts[index] as usize == self.len {
+            *self = Self::new(self.len, block);
+            return;
+        }
+        self.set_index(i, index);
+    }
+
+    /// Drops unused palette entries and shrinks indices, returns to uniform if possible
+    pub fn compact(&mut self) {
+        if self.counts.iter().all(|count| *count > 0) {
+            return;
+        }
+        let mut remap = vec![0; self.palette.len()];
+        let (mut palette, mut counts) = (Vec::new(), Vec::new());
+        for (old, block) in self.palette.iter().enumerate() {
+            if self.counts[old] > 0 {
+                remap[old] = palette.len();
+                palette.push(*block);
+                counts.push(self.counts[old]);
+            }
+        }
+        let indices: Vec<usize> = (0..self.len).map(|i| remap[self.get_index(i)]).collect();
+        self.palette = palette;
+        self.counts = counts;
+        self.bits = bits_for(self.palette.len());
+        self.data = vec![0; words_for(self.len, self.bits)];
+        if self.bits == 0 {
+            return;
+        }
+        for (i, index) in indices.into_iter().enumerate() {
+            self.set_index(i, index);
+        }
+    }
+
+    /// Palette length, palette as raw blocks, bits per index, then packed words (little endian)
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&(self.palette.len() as u32).to_le_bytes())?;
+        for block in &self.palette {
+            out.write_all(&block.to_raw().to_le_bytes())?;
+        }
+        out.write_all(&[self.bits as u8])?;
+        for word in &self.data {
+            out.write_all(&word.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(len: usize, input: &mut impl Read) -> io::Result<Self> {
+        let mut buf = [0; 8];
+        input.read_exact(&mut buf[..4])?;
+        let palette_len = u32::from_le_bytes(buf[..4].try_into().unwrap()) as usize;
+        if palette_len == 0 || palette_len > len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid palette length",
+            ));
+        }
+        let mut palette = Vec::with_capacity(palette_len);
+        for _ in 0..palette_len {
+            input.read_exact(&mut buf[..4])?;
+            palette.push(Block::from_raw(u32::from_le_bytes(
+                buf[..4].try_into().unwrap(),
+            )));
+        }
+        input.read_exact(&mut buf[..1])?;
+        let bits = buf[0] as u32;
+        if bits > 32 || (bits == 0) != (palette_len == 1) || bits < bits_for(palette_len) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid index bits",
+            ));
+        }
+        let mut data = Vec::with_capacity(words_for(len, bits));
+        for _ in 0..words_for(len, bits) {
+            input.read_exact(&mut buf)?;
+            data.push(u64::from_le_bytes(buf));
+        }
+        let mut blocks = Self {
+            len,
+            counts: vec![0; palette.len()],
+            palette,
+            bits,
+            data,
+        };
+        if bits == 0 {
+            blocks.counts[0] = len as u32;
+            return Ok(blocks);
+        }
+        for i in 0..len {
+            let index = blocks.get_index(i);
+            let Some(count) = blocks.counts.get_mut(index) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "palette index out of range",
+                ));
+            };
+            *count += 1;
+        }
+        Ok(blocks)
+    }
+
+    fn per_word(&self) -> usize {
+        (u64::BITS / self.bits) as usize
+    }
+    fn get_index(&self, i: usize) -> usize {
+        let per_word = self.per_word();
+        let shift = (i % per_word) as u32 * self.bits;
+        ((self.data[i / per_word] >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+    fn set_index(&mut self, i: usize, index: usize) {
+        let per_word = self.per_word();
+        let shift = (i % per_word) as u32 * self.bits;
+        let mask = ((1_u64 << self.bits) - 1) << shift;
+        let word = &mut self.data[i / per_word];
+        *word = (*word & !mask) | ((index as u64) << shift);
+    }
+    fn repack(&mut self, bits: u32) {
+        let indices: Vec<usize> = if self.bits == 0 {
+            vec![0; self.len]
+        } else {
+            (0..self.len).map(|i| self.get_index(i)).collect()
+        };
+        self.bits = bits;
+        self.data = vec![0; words_for(self.len, bits)];
+        for (i, index) in indices.into_iter().enumerate() {
+            self.set_index(i, index);
+        }
+    }
+}
+
+/// Bits needed to index a palette of `len` entries
+fn bits_for(len: usize) -> u32 {
+    if len <= 1 {
+        0
+    } else {
+        usize::BITS - (len - 1).leading_zeros()
+    }
+}
+
+fn words_for(len: usize, bits: u32) -> usize {
+    match u64::BITS.checked_div(bits) {
+        Some(per_word) => len.div_ceil(per_word as usize),
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::blocks::BlockId;
+
+    const LEN: usize = 4096;
+
+    fn block(n: u32) -> Block {
+        Block::solid(BlockId(n))
+    }
+
+    fn round_trip(blocks: &PalettedBlocks) -> PalettedBlocks {
+        let mut bytes = Vec::new();
+        blocks.write_to(&mut bytes).unwrap();
+        PalettedBlocks::read_from(blocks.len, &mut bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn get_set_across_bit_widths() {
+        let mut blocks = PalettedBlocks::new(LEN, Block::Air);
+        let expected = |i: usize| match i % 300 {
+            0 => Block::Air,
+            n => block(n as u32),
+        };
+        for i in 0..LEN {
+            blocks.set(i, expected(i));
+            // Indices grow up to 9 bits, already written ones must survive every repack
+            assert!((0..=i).all(|j| blocks.get(j) == expected(j)), "at {i}");
+        }
+        assert_eq!(blocks.bits, 9);
+        let read = round_trip(&blocks);
+        assert!((0..LEN).all(|i| read.get(i) == expected(i)));
+    }
+
+    #[test]
+    fn uniform_mixed_uniform() {
+        let mut blocks = PalettedBlocks::new(LEN, Block::Air);
+        for i in (0..LEN).step_by(7) {
+            blocks.set(i, block(1));
+        }
+        assert!(blocks.bits > 0);
+        for i in (0..LEN).step_by(7) {
+            blocks.set(i, Block::Air);
+        }
+        assert_eq!(blocks.bits, 0);
+        assert_eq!(blocks.palette, [Block::Air]);
+        // Filled with another block is uniform too
+        for i in 0..LEN {
+            blocks.set(i, block(2));
+        }
+        assert_eq!(blocks.bits, 0);
+        assert_eq!(blocks.get(LEN - 1), block(2));
+        assert_eq!(round_trip(&blocks).palette, [block(2)]);
+    }
+
+    #[test]
+    fn unused_entries_are_reused() {
+        let mut blocks = PalettedBlocks::new(LEN, Block::Air);
+        for n in 1..100 {
+            blocks.set(5, block(n));
+        }
+        assert_eq!(blocks.palette.len(), 2);
+        assert_eq!(blocks.get(5), block(99));
+        assert!((0..LEN)
+            .filter(|i| *i != 5)
+            .all(|i| blocks.get(i) == Block::Air));
+    }
+
+    #[test]
+    fn compact_drops_free_entries() {
+        let mut blocks = PalettedBlocks::new(LEN, Block::Air);
+        for n in 1..=4 {
+            blocks.set(n as usize, block(n));
+        }
+        for n in 1..=3 {
+            blocks.set(n as usize, Block::Air);
+        }
+        blocks.compact();
+        assert_eq!(blocks.palette, [Block::Air, block(4)]);
+        assert_eq!(blocks.bits, 1);
+        assert_eq!(blocks.get(4), block(4));
+        assert_eq!(blocks.get(1), Block::Air);
+    }
+}
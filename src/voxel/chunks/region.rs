@@ -0,0 +1,209 @@
+/// Region files: [`REGION_SIZE`]x[`REGION_SIZE`] chunks per file in `<world>/region/r.<x>.<z>.rgn`
+///
+/// Layout (little endian):
+/// `b"RCRG"`, region version `u32`, offset table of `(offset u32, len u32)` per chunk
+/// (`len` 0 means not saved), then chunk payloads.
+/// A payload is the chunk version byte followed by the zlib compressed chunk data
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::utils::{thiserror, HashMap};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use thiserror::Error;
+
+use crate::{prelude::*, utils::write_atomic};
+
+use super::chunk::Chunk;
+
+pub const REGION_SIZE: i32 = 32;
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE) as usize;
+const MAGIC: &[u8; 4] = b"RCRG";
+const REGION_VERSION: u32 = 1;
+/// Version of the chunk payload, bump when the block storage changes and migrate in [`decode_chunk`]
+const CHUNK_VERSION: u8 = 1;
+const HEADER_LEN: usize = 8 + REGION_CHUNKS * 8;
+
+/// Directory of the current world, chunks are saved into it
+#[derive(Resource, Clone, Debug)]
+pub struct WorldDir(pub PathBuf);
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum RegionError {
+    #[error("Region io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Unsupported region version {0}")]
+    UnsupportedRegionVersion(u32),
+    #[error("Unsupported chunk version {0}")]
+    UnsupportedChunkVersion(u8),
+    #[error("Corrupted region file: {0}")]
+    Corrupted(&'static str),
+}
+
+/// Chunks read from one region, `None` for chunks that were never saved
+pub type RegionChunks = Vec<(IVec2, Result<Option<Chunk>, RegionError>)>;
+
+/// Loads chunks of the region `region` reading its file once.
+/// The whole load fails when the file can't be read, single chunks when they can't be decoded
+pub fn load_chunks(
+    world: &Path,
+    region: IVec2,
+    positions: &[IVec2],
+) -> Result<RegionChunks, RegionError> {
+    let bytes = match fs::read(region_path(world, region)) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(positions.iter().map(|pos| (*pos, Ok(None))).collect());
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let parsed = Region::parse(&bytes)?;
+    Ok(positions
+        .iter()
+        .map(|pos| {
+            debug_assert_eq!(region_pos(*pos), region);
+            let chunk = match parsed.chunks[chunk_index(*pos)] {
+                Some(payload) => decode_chunk(*pos, payload).map(Some),
+                None => Ok(None),
+            };
+            (*pos, chunk)
+        })
+        .collect())
+}
+
+/// Saves chunks grouped by region, each region file is rewritten once
+pub fn save_chunks<'a>(
+    world: &Path,
+    chunks: impl IntoIterator<Item = &'a Chunk>,
+) -> Result<(), RegionError> {
+    let mut regions: HashMap<IVec2, Vec<&Chunk>> = HashMap::new();
+    for chunk in chunks {
+        regions
+            .entry(region_pos(chunk.pos))
+            .or_default()
+            .push(chunk);
+    }
+    for (region, chunks) in regions {
+        let path = region_path(world, region);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut payloads: Vec<Option<Vec<u8>>> = if bytes.is_empty() {
+            vec![None; REGION_CHUNKS]
+        } else {
+            Region::parse(&bytes)?
+                .chunks
+                .iter()
+                .map(|payload| payload.map(<[u8]>::to_vec))
+                .collect()
+        };
+        for chunk in chunks {
+            payloads[chunk_index(chunk.pos)] = Some(encode_chunk(chunk)?);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(&path, Region::write(&payloads))?;
+    }
+    Ok(())
+}
+
+/// Parsed region file, payloads borrow the file bytes
+struct Region<'a> {
+    chunks: Vec<Option<&'a [u8]>>,
+}
+
+impl<'a> Region<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self, RegionError> {
+        if bytes.len() < 8 || &bytes[..4] != MAGIC {
+            return Err(RegionError::Corrupted("bad magic"));
+        }
+        let version = read_u32(bytes, 4);
+        if version != REGION_VERSION {
+            return Err(RegionError::UnsupportedRegionVersion(version));
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(RegionError::Corrupted("truncated offset table"));
+        }
+        let mut chunks = Vec::with_capacity(REGION_CHUNKS);
+        for i in 0..REGION_CHUNKS {
+            let offset = read_u32(bytes, 8 + i * 8) as usize;
+            let len = read_u32(bytes, 12 + i * 8) as usize;
+            if len == 0 {
+                chunks.push(None);
+                continue;
+            }
+            let payload = offset
+                .checked_add(len)
+                .and_then(|end| bytes.get(offset..end))
+                .ok_or(RegionError::Corrupted("chunk payload out of bounds"))?;
+            chunks.push(Some(payload));
+        }
+        Ok(Self { chunks })
+    }
+
+    fn write(payloads: &[Option<Vec<u8>>]) -> Vec<u8> {
+        let mut table = Vec::with_capacity(HEADER_LEN);
+        table.extend_from_slice(MAGIC);
+        table.extend_from_slice(&REGION_VERSION.to_le_bytes());
+        let mut data = Vec::new();
+        for payload in payloads {
+            let (offset, len) = match payload {
+                Some(payload) => {
+                    let offset = HEADER_LEN + data.len();
+                    data.extend_from_slice(payload);
+                    (offset as u32, payload.len() as u32)
+                }
+                None => (0, 0),
+            };
+            table.extend_from_slice(&offset.to_le_bytes());
+            table.extend_from_slice(&len.to_le_bytes());
+        }
+        table.append(&mut data);
+        table
+    }
+}
+
+fn encode_chunk(chunk: &Chunk) -> io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(vec![CHUNK_VERSION], Compression::default());
+    chunk.write_to(&mut encoder)?;
+    encoder.finish()
+}
+
+fn decode_chunk(pos: IVec2, payload: &[u8]) -> Result<Chunk, RegionError> {
+    let (&version, data) = payload
+        .split_first()
+        .ok_or(RegionError::Corrupted("empty chunk payload"))?;
+    let mut decoder = ZlibDecoder::new(data);
+    match version {
+        CHUNK_VERSION => Ok(Chunk::read_from(pos, &mut decoder)?),
+        version => Err(RegionError::UnsupportedChunkVersion(version)),
+    }
+}
+
+/// Region containing the chunk at chunk coordinate `chunk`
+pub fn region_pos(chunk: IVec2) -> IVec2 {
+    IVec2::new(
+        chunk.x.div_euclid(REGION_SIZE),
+        chunk.y.div_euclid(REGION_SIZE),
+    )
+}
+
+/// Index of the chunk inside its region offset table
+fn chunk_index(chunk: IVec2) -> usize {
+    (chunk.y.rem_euclid(REGION_SIZE) * REGION_SIZE + chunk.x.rem_euclid(REGION_SIZE)) as usize
+}
+
+fn region_path(world: &Path, region: IVec2) -> PathBuf {
+    world
+        .join("region")
+        .join(format!("r.{}.{}.rgn", region.x, region.y))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
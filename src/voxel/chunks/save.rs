@@ -0,0 +1,169 @@
+/// Saving edited chunks in the background: periodic autosave, chunks unloaded while dirty
+/// and a blocking flush on [`AppExit`](bevy::app::AppExit)
+use std::sync::Arc;
+
+use bevy::tasks::{block_on, futures_lite::future, IoTaskPool, Task};
+
+use crate::prelude::*;
+
+use super::{
+    chunk::Chunk,
+    region::{RegionError, WorldDir},
+    save_chunks,
+};
+
+/// Seconds between autosaves
+const AUTOSAVE_INTERVAL: f32 = 30.;
+
+#[derive(Resource)]
+pub struct ChunkSaver {
+    /// Dirty chunks that were unloaded, saved with the next write
+    pending: Vec<Chunk>,
+    /// Chunks of a failed write, tried again with the next autosave
+    retry: Vec<Chunk>,
+    /// Chunks written by `task`, only one write runs at a time so regions aren't written twice at once
+    saving: Arc<Vec<Chunk>>,
+    task: Option<Task<Result<(), RegionError>>>,
+    timer: Timer,
+}
+
+impl Default for ChunkSaver {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+            retry: Vec::new(),
+            saving: Arc::default(),
+            task: None,
+            timer: Timer::from_seconds(AUTOSAVE_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+impl ChunkSaver {
+    /// Keeps an unloaded dirty chunk until it is written
+    pub fn push(&mut self, chunk: Chunk) {
+        self.pending.retain(|c| c.pos != chunk.pos);
+        self.retry.retain(|c| c.pos != chunk.pos);
+        self.pending.push(chunk);
+    }
+    /// Newest not yet written copy of the chunk, region files may still have an older one
+    pub fn unsaved(&self, pos: IVec2) -> Option<Chunk> {
+        self.pending
+            .iter()
+            .chain(self.retry.iter())
+            .chain(self.saving.iter())
+            .find(|c| c.pos == pos)
+            .cloned()
+    }
+    /// Clears the write once it's finished
+    fn poll(&mut self) {
+        let Some(task) = &mut self.task else {
+            return;
+        };
+        if let Some(result) = block_on(future::poll_once(task)) {
+            self.task = None;
+            self.finish(result);
+        }
+    }
+    fn wait(&mut self) {
+        if let Some(task) = self.task.take() {
+            self.finish(block_on(task));
+        }
+    }
+    /// Keeps the chunks of a failed write for a retry, unless a newer copy is already pending
+    fn finish(&mut self, result: Result<(), RegionError>) {
+        let saving = std::mem::take(&mut self.saving);
+        if let Err(err) = result {
+            error!("Failed to save chunks, retrying with the next autosave: {err}");
+            let saving = Arc::try_unwrap(saving).unwrap_or_else(|saving| (*saving).clone());
+            for chunk in saving {
+                if !self.pending.iter().any(|c| c.pos == chunk.pos) {
+                    self.retry.push(chunk);
+                }
+            }
+        }
+    }
+}
+
+/// Takes copies of dirty loaded chunks and marks them clean
+fn take_dirty(chunks: &mut Query<&mut Chunk>) -> Vec<Chunk> {
+    chunks
+        .iter_mut()
+        .filter(|chunk| chunk.is_dirty())
+        .map(|mut chunk| {
+            chunk.clear_dirty();
+            chunk.clone()
+        })
+        .collect()
+}
+
+pub fn autosave(
+    mut saver: ResMut<ChunkSaver>,
+    mut chunks: Query<&mut Chunk>,
+    world: Res<WorldDir>,
+    time: Res<Time>,
+) {
+    saver.poll();
+    saver.timer.tick(time.delta());
+    if saver.task.is_some() || (saver.pending.is_empty() && !saver.timer.finished()) {
+        return;
+    }
+    // Later copies of a chunk overwrite earlier ones, so retried chunks go first
+    let mut to_save = Vec::new();
+    let autosave = saver.timer.finished();
+    if autosave {
+        to_save.append(&mut saver.retry);
+    }
+    to_save.append(&mut saver.pending);
+    if autosave {
+        to_save.extend(take_dirty(&mut chunks));
+    }
+    if to_save.is_empty() {
+        return;
+    }
+    let saving = Arc::new(to_save);
+    let world = world.0.clone();
+    let chunks = saving.clone();
+    saver.saving = saving;
+    saver.task = Some(IoTaskPool::get().spawn(async move { save_chunks(&world, chunks.iter()) }));
+}
+
+pub fn save_on_exit(
+    mut saver: ResMut<ChunkSaver>,
+    mut chunks: Query<&mut Chunk>,
+    world: Res<WorldDir>,
+) {
+    saver.wait();
+    let mut to_save = std::mem::take(&mut saver.retry);
+    to_save.append(&mut saver.pending);
+    to_save.extend(take_dirty(&mut chunks));
+    if let Err(err) = save_chunks(&world.0, &to_save) {
+        error!("Failed to save chunks: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_writes_are_retried() {
+        let mut saver = ChunkSaver {
+            saving: Arc::new(vec![Chunk::new_air(IVec2::ZERO), Chunk::new_air(IVec2::X)]),
+            ..default()
+        };
+        saver.finish(Err(RegionError::Corrupted("test")));
+        assert!(saver.saving.is_empty());
+        assert!(saver.unsaved(IVec2::ZERO).is_some());
+        assert!(saver.unsaved(IVec2::X).is_some());
+
+        // A newer unloaded copy replaces the failed one
+        saver.push(Chunk::new_air(IVec2::X));
+        assert_eq!(saver.retry.len(), 1);
+        assert_eq!(saver.pending.len(), 1);
+
+        saver.saving = Arc::new(vec![Chunk::new_air(IVec2::Y)]);
+        saver.finish(Ok(()));
+        assert!(saver.unsaved(IVec2::Y).is_none());
+    }
+}
@@ -0,0 +1,115 @@
+/// World generation
+/// Generators are pure: same seed and position always give the same chunk
+pub mod terrain;
+
+use std::sync::Arc;
+
+use bevy::utils::thiserror;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{prelude::*, voxel::blocks::BlockId};
+
+use super::chunks::chunk::Chunk;
+
+pub const DEFAULT_SEED: u64 = 0x5EED;
+
+pub trait WorldGenerator: Send + Sync {
+    /// Generates blocks of chunk at chunk coordinate `pos`
+    fn generate(&self, pos: IVec2) -> Chunk;
+    /// Climate of world column `x`, `z`, colors grass and foliage
+    fn climate(&self, _x: i32, _z: i32) -> Climate {
+        Climate::default()
+    }
+}
+
+/// Climate of a world column, both values in `0..=1`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Climate {
+    pub temperature: f32,
+    pub humidity: f32,
+}
+
+impl Default for Climate {
+    fn default() -> Self {
+        Self {
+            temperature: 0.5,
+            humidity: 0.5,
+        }
+    }
+}
+
+impl Climate {
+    pub fn lerp(self, other: Self, t: f32) -> Self {
+        Self {
+            temperature: self.temperature + (other.temperature - self.temperature) * t,
+            humidity: self.humidity + (other.humidity - self.humidity) * t,
+        }
+    }
+}
+
+/// Generator saved in the world metadata
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GeneratorKind {
+    /// Noise heightmap, see [`terrain::TerrainGenerator`]
+    Terrain,
+}
+
+impl GeneratorKind {
+    pub fn create(self, seed: u64, blocks: TerrainBlocks) -> Arc<dyn WorldGenerator> {
+        match self {
+            Self::Terrain => Arc::new(terrain::TerrainGenerator::new(seed, blocks)),
+        }
+    }
+}
+
+/// Generator of the current world, cheap to clone into tasks
+#[derive(Resource, Clone)]
+pub struct WorldGen(pub Arc<dyn WorldGenerator>);
+
+impl WorldGen {
+    /// Generated chunks are not dirty, they can always be generated again
+    pub fn generate(&self, pos: IVec2) -> Chunk {
+        let mut chunk = self.0.generate(pos);
+        chunk.clear_dirty();
+        chunk
+    }
+    pub fn climate(&self, x: i32, z: i32) -> Climate {
+        self.0.climate(x, z)
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Block type `{0}` used by the generator is not loaded")]
+pub struct MissingBlockType(pub &'static str);
+
+/// Block ids used by generators, resolved from block type names
+#[derive(Clone, Copy, Debug)]
+pub struct TerrainBlocks {
+    pub grass: BlockId,
+    pub dirt: BlockId,
+    pub stone: BlockId,
+}
+
+impl TerrainBlocks {
+    pub const GRASS: &'static str = "core:grass";
+    pub const DIRT: &'static str = "core:dirt";
+    pub const STONE: &'static str = "core:cobblestone";
+
+    pub fn from_names(get_id: impl Fn(&str) -> Option<BlockId>) -> Result<Self, MissingBlockType> {
+        let id = |name| get_id(name).ok_or(MissingBlockType(name));
+        Ok(Self {
+            grass: id(Self::GRASS)?,
+            dirt: id(Self::DIRT)?,
+            stone: id(Self::STONE)?,
+        })
+    }
+    /// Every block is `id`, for worlds generated without their block types
+    pub fn all(id: BlockId) -> Self {
+        Self {
+            grass: id,
+            dirt: id,
+            stone: id,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use crate::{
+    prelude::*,
+    voxel::{blocks::Block, chunks::chunk::Chunk},
+};
+
+use super::{Climate, TerrainBlocks, WorldGenerator};
+
+const BASE_HEIGHT: f64 = 40.;
+const HEIGHT_AMPLITUDE: f64 = 20.;
+const MIN_DIRT_DEPTH: f64 = 2.;
+const DIRT_DEPTH_AMPLITUDE: f64 = 3.;
+/// Climate changes over hundreds of blocks
+const CLIMATE_FREQUENCY: f64 = 0.004;
+
+/// Heightmap from layered (fbm) perlin noise: grass on top, dirt, then cobblestone
+pub struct TerrainGenerator {
+    blocks: TerrainBlocks,
+    height: Fbm<Perlin>,
+    dirt_depth: Perlin,
+    temperature: Perlin,
+    humidity: Perlin,
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u64, blocks: TerrainBlocks) -> Self {
+        // noise takes u32 seeds, fold high bits so they still matter
+        let seed32 = (seed ^ (seed >> 32)) as u32;
+        Self {
+            blocks,
+            height: Fbm::<Perlin>::new(seed32)
+                .set_octaves(4)
+                .set_frequency(0.008),
+            dirt_depth: Perlin::new(seed32.wrapping_add(1)),
+            temperature: Perlin::new(seed32.wrapping_add(2)),
+            humidity: Perlin::new(seed32.wrapping_add(3)),
+        }
+    }
+
+    /// Height of the top (grass) block at world column `x`, `z`
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let h = BASE_HEIGHT + self.height.get([x as f64, z as f64]) * HEIGHT_AMPLITUDE;
+        (h.round() as i32).clamp(1, CHUNK_H as i32 - 1)
+    }
+}
+
+impl WorldGenerator for TerrainGenerator {
+    fn generate(&self, pos: IVec2) -> Chunk {
+        let mut chunk = Chunk::new_air(pos);
+        for x in 0..CHUNK_W {
+            for z in 0..CHUNK_D {
+                let wx = pos.x * CHUNK_W as i32 + x as i32;
+                let wz = pos.y * CHUNK_D as i32 + z as i32;
+                let height = self.height_at(wx, wz);
+                let dirt_depth = (MIN_DIRT_DEPTH
+                    + (self.dirt_depth.get([wx as f64 * 0.1, wz as f64 * 0.1]) + 1.) / 2.
+                        * DIRT_DEPTH_AMPLITUDE) as i32;
+                for y in 0..=height {
+                    let id = if y == height {
+                        self.blocks.grass
+                    } else if y >= height - dirt_depth {
+                        self.blocks.dirt
+                    } else {
+                        self.blocks.stone
+                    };
+                    chunk.set(x, y as usize, z, Block::solid(id));
+                }
+            }
+        }
+        chunk
+    }
+
+    fn climate(&self, x: i32, z: i32) -> Climate {
+        let point = [x as f64 * CLIMATE_FREQUENCY, z as f64 * CLIMATE_FREQUENCY];
+        let sample = |noise: &Perlin| ((noise.get(point) + 1.) / 2.).clamp(0., 1.) as f32;
+        Climate {
+            temperature: sample(&self.temperature),
+            humidity: sample(&self.humidity),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::{blocks::BlockId, generator::DEFAULT_SEED};
+
+    const BLOCKS: TerrainBlocks = TerrainBlocks {
+        grass: BlockId(1),
+        dirt: BlockId(2),
+        stone: BlockId(3),
+    };
+
+    fn columns() -> impl Iterator<Item = (usize, usize)> {
+        (0..CHUNK_W).flat_map(|x| (0..CHUNK_D).map(move |z| (x, z)))
+    }
+
+    fn blocks(chunk: &Chunk) -> Vec<Block> {
+        columns()
+            .flat_map(|(x, z)| (0..CHUNK_H).map(move |y| (x, y, z)))
+            .map(|(x, y, z)| chunk.get(x, y, z).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_and_pos_give_same_chunk() {
+        for pos in [IVec2::ZERO, IVec2::new(-3, 7)] {
+            let a = TerrainGenerator::new(42, BLOCKS).generate(pos);
+            let b = TerrainGenerator::new(42, BLOCKS).generate(pos);
+            assert_eq!(blocks(&a), blocks(&b));
+        }
+    }
+
+    #[test]
+    fn seed_changes_terrain() {
+        let pos = IVec2::new(2, -1);
+        let a = TerrainGenerator::new(1, BLOCKS).generate(pos);
+        let b = TerrainGenerator::new(2, BLOCKS).generate(pos);
+        assert_ne!(blocks(&a), blocks(&b));
+    }
+
+    #[test]
+    fn grass_over_dirt_over_stone() {
+        let generator = TerrainGenerator::new(DEFAULT_SEED, BLOCKS);
+        let pos = IVec2::new(5, -4);
+        let chunk = generator.generate(pos);
+        for (x, z) in columns() {
+            let height = generator.height_at(
+                pos.x * CHUNK_W as i32 + x as i32,
+                pos.y * CHUNK_D as i32 + z as i32,
+            ) as usize;
+            let id = |y| chunk.get(x, y, z).unwrap().id();
+            assert_eq!(id(height), Some(BLOCKS.grass));
+            assert!((height + 1..CHUNK_H).all(|y| id(y).is_none()));
+            // Dirt right under the grass, stone below it down to the bottom
+            let dirt_end = (0..height)
+                .rev()
+                .find(|y| id(*y) != Some(BLOCKS.dirt))
+                .map_or(0, |y| y + 1);
+            assert!(dirt_end < height, "no dirt under the grass at {x}, {z}");
+            assert!((0..dirt_end).all(|y| id(y) == Some(BLOCKS.stone)));
+        }
+    }
+}
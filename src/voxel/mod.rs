@@ -1,7 +1,10 @@
 pub mod blocks;
 pub mod chunks;
+pub mod generator;
+pub mod world;
 
 use blocks::BlockPlugin;
+use chunks::ChunksPlugin;
 
 use crate::prelude::*;
 
@@ -9,6 +12,6 @@ pub struct VoxelPlugin;
 
 impl Plugin for VoxelPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(BlockPlugin);
+        app.add_plugins((BlockPlugin, ChunksPlugin));
     }
 }
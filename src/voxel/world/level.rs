@@ -0,0 +1,91 @@
+/// `level.ron`, metadata of a world
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::asset::ron;
+use serde::{Deserialize, Serialize};
+
+use crate::{prelude::*, utils::write_atomic, voxel::generator::GeneratorKind};
+
+use super::WorldError;
+
+const LEVEL_FILE: &str = "level.ron";
+
+#[derive(Resource, Clone, Debug, Serialize, Deserialize)]
+pub struct Level {
+    pub name: String,
+    pub seed: u64,
+    pub generator: GeneratorKind,
+    /// Unix time in seconds
+    pub created: u64,
+    /// Unix time in seconds
+    pub last_played: u64,
+    /// `None` until the world is left for the first time
+    pub player: Option<PlayerPos>,
+    /// Game version that saved the world last
+    pub version: String,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PlayerPos {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+}
+
+impl PlayerPos {
+    pub fn from_transform(transform: &Transform) -> Self {
+        Self {
+            translation: transform.translation.to_array(),
+            rotation: transform.rotation.to_array(),
+        }
+    }
+    pub fn to_transform(self) -> Transform {
+        Transform::from_translation(Vec3::from_array(self.translation))
+            .with_rotation(Quat::from_array(self.rotation))
+    }
+}
+
+impl Level {
+    pub fn new(name: String, seed: u64, generator: GeneratorKind) -> Self {
+        let now = now();
+        Self {
+            name,
+            seed,
+            generator,
+            created: now,
+            last_played: now,
+            player: None,
+            version: GAME_VERSION.to_string(),
+        }
+    }
+    pub fn load(world: &Path) -> Result<Self, WorldError> {
+        Ok(ron::de::from_bytes(&fs::read(level_path(world))?)?)
+    }
+    pub fn save(&self, world: &Path) -> Result<(), WorldError> {
+        fs::create_dir_all(world)?;
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        write_atomic(&level_path(world), text)?;
+        Ok(())
+    }
+    /// Marks the world as played now by this game version
+    pub fn touch(&mut self) {
+        self.last_played = now();
+        self.version = GAME_VERSION.to_string();
+    }
+}
+
+pub const GAME_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub(super) fn level_path(world: &Path) -> PathBuf {
+    world.join(LEVEL_FILE)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,262 @@
+/// Worlds in the saves directory: listing, creating, renaming, duplicating and deleting.
+/// A world is a directory with [`level::Level`] metadata, block registry and region files
+pub mod level;
+
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use bevy::{asset::ron, utils::thiserror};
+use thiserror::Error;
+
+use crate::{prelude::*, voxel::generator::GeneratorKind};
+
+use level::Level;
+
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum WorldError {
+    #[error("World io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Could not parse level: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("Could not write level: {0}")]
+    Write(#[from] ron::Error),
+    #[error("World {0} does not exist")]
+    NotFound(String),
+    #[error("Invalid world id {0:?}")]
+    InvalidId(String),
+}
+
+/// World in the saves directory, `id` is its directory name
+#[derive(Clone, Debug)]
+pub struct WorldEntry {
+    pub id: String,
+    pub level: Level,
+}
+
+/// Directory with all worlds
+#[derive(Resource, Clone, Debug)]
+pub struct Saves {
+    root: PathBuf,
+}
+
+impl Saves {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+    pub fn world_dir(&self, id: &str) -> PathBuf {
+        self.root.join(id)
+    }
+    pub fn get(&self, id: &str) -> Result<WorldEntry, WorldError> {
+        let dir = self.existing_dir(id)?;
+        Ok(WorldEntry {
+            id: id.to_string(),
+            level: Level::load(&dir)?,
+        })
+    }
+    /// New world in a directory named after `name`
+    pub fn create(
+        &self,
+        name: &str,
+        seed: u64,
+        generator: GeneratorKind,
+    ) -> Result<WorldEntry, WorldError> {
+        let id = self.free_id(name);
+        let level = Level::new(name.to_string(), seed, generator);
+        level.save(&self.world_dir(&id))?;
+        Ok(WorldEntry { id, level })
+    }
+    /// Writes new metadata into a world directory without a readable level,
+    /// its chunks and block registry are kept
+    pub fn adopt(
+        &self,
+        id: &str,
+        seed: u64,
+        generator: GeneratorKind,
+    ) -> Result<WorldEntry, WorldError> {
+        check_id(id)?;
+        let dir = self.world_dir(id);
+        if !dir.is_dir() {
+            return Err(WorldError::NotFound(id.to_string()));
+        }
+        let level = Level::new(id.to_string(), seed, generator);
+        level.save(&dir)?;
+        Ok(WorldEntry {
+            id: id.to_string(),
+            level,
+        })
+    }
+}
+
+// Backend of the world selection screen in `GameState::Menu`, only tests use it until it exists
+#[allow(dead_code)]
+impl Saves {
+    /// Worlds with readable metadata, last played first
+    pub fn list(&self) -> Result<Vec<WorldEntry>, WorldError> {
+        let dir = match fs::read_dir(&self.root) {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut worlds = Vec::new();
+        for entry in dir {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let Some(id) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if !level::level_path(&entry.path()).exists() {
+                continue;
+            }
+            match Level::load(&entry.path()) {
+                Ok(level) => worlds.push(WorldEntry { id, level }),
+                Err(err) => error!("Skipping world {id}: {err}"),
+            }
+        }
+        worlds.sort_by_key(|world| std::cmp::Reverse(world.level.last_played));
+        Ok(worlds)
+    }
+    /// Changes the display name, the directory stays the same
+    pub fn rename(&self, id: &str, name: &str) -> Result<(), WorldError> {
+        let dir = self.existing_dir(id)?;
+        let mut level = Level::load(&dir)?;
+        level.name = name.to_string();
+        level.save(&dir)
+    }
+    /// Copy of the whole world under a new name
+    pub fn duplicate(&self, id: &str, name: &str) -> Result<WorldEntry, WorldError> {
+        let from = self.existing_dir(id)?;
+        let new_id = self.free_id(name);
+        let to = self.world_dir(&new_id);
+        copy_dir(&from, &to)?;
+        let mut level = Level::load(&to)?;
+        level.name = name.to_string();
+        level.save(&to)?;
+        Ok(WorldEntry { id: new_id, level })
+    }
+    pub fn delete(&self, id: &str) -> Result<(), WorldError> {
+        fs::remove_dir_all(self.existing_dir(id)?)?;
+        Ok(())
+    }
+}
+
+impl Saves {
+    fn existing_dir(&self, id: &str) -> Result<PathBuf, WorldError> {
+        check_id(id)?;
+        let dir = self.world_dir(id);
+        if level::level_path(&dir).exists() {
+            Ok(dir)
+        } else {
+            Err(WorldError::NotFound(id.to_string()))
+        }
+    }
+    /// Directory name from the world name, numbered if it is taken
+    fn free_id(&self, name: &str) -> String {
+        let mut base: String = name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        if base.is_empty() {
+            base = "world".to_string();
+        }
+        let mut id = base.clone();
+        let mut n = 1;
+        while self.world_dir(&id).exists() {
+            n += 1;
+            id = format!("{base}_{n}");
+        }
+        id
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Ids are single directory names, anything else could reach outside the saves directory
+fn check_id(id: &str) -> Result<(), WorldError> {
+    let mut components = Path::new(id).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(name)), None) if name == id && !id.contains(['/', '\\']) => Ok(()),
+        _ => Err(WorldError::InvalidId(id.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_stay_inside_saves() {
+        assert!(check_id("world_2").is_ok());
+        for id in ["", ".", "..", "../world", "a/b", "a\\b", "/tmp", "world/"] {
+            assert!(check_id(id).is_err(), "{id:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn manage_worlds() {
+        let root = std::env::temp_dir().join(format!("rustcraft-saves-{}", std::process::id()));
+        let saves = Saves::new(&root);
+        assert!(saves.list().unwrap().is_empty());
+
+        let first = saves.create("My World", 1, GeneratorKind::Terrain).unwrap();
+        let second = saves.create("My World", 2, GeneratorKind::Terrain).unwrap();
+        assert_eq!(first.id, "My_World");
+        assert_eq!(second.id, "My_World_2");
+
+        let mut level = second.level.clone();
+        level.last_played = first.level.last_played + 10;
+        level.save(&saves.world_dir(&second.id)).unwrap();
+        let ids: Vec<_> = saves.list().unwrap().into_iter().map(|w| w.id).collect();
+        assert_eq!(ids, ["My_World_2", "My_World"]);
+
+        saves.rename(&first.id, "Renamed").unwrap();
+        assert_eq!(saves.get(&first.id).unwrap().level.name, "Renamed");
+
+        fs::create_dir_all(saves.world_dir(&first.id).join("region")).unwrap();
+        fs::write(saves.world_dir(&first.id).join("region/r.0.0"), [1, 2, 3]).unwrap();
+        let copy = saves.duplicate(&first.id, "Copy").unwrap();
+        assert_eq!(copy.id, "Copy");
+        assert_eq!(copy.level.seed, 1);
+        assert_eq!(
+            fs::read(saves.world_dir(&copy.id).join("region/r.0.0")).unwrap(),
+            [1, 2, 3]
+        );
+
+        fs::remove_file(level::level_path(&saves.world_dir(&copy.id))).unwrap();
+        let adopted = saves.adopt(&copy.id, 7, GeneratorKind::Terrain).unwrap();
+        assert_eq!(adopted.level.seed, 7);
+        assert!(saves.world_dir(&copy.id).join("region/r.0.0").exists());
+
+        assert!(matches!(saves.delete(".."), Err(WorldError::InvalidId(_))));
+        saves.delete(&second.id).unwrap();
+        assert!(!saves.world_dir(&second.id).exists());
+        assert!(matches!(
+            saves.get(&second.id),
+            Err(WorldError::NotFound(_))
+        ));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}